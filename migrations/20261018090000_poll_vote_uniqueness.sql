@@ -0,0 +1,45 @@
+-- answering the question from the initial migration:
+-- yes, by copying poll_id and poll_type onto every vote
+-- the rules can be enforced by unique indexes, which
+-- concurrent requests can't race past like program logic
+
+-- targets for the composite foreign keys below, these make sure
+-- the copied columns can never diverge from the originals
+alter table poll add constraint unique_id_poll_type unique (id, poll_type);
+alter table poll_option add constraint unique_id_poll_id unique (id, poll_id);
+
+alter table poll_vote
+    add column poll_id bigint,
+    add column poll_type poll_type;
+
+update poll_vote
+set poll_id = poll.id, poll_type = poll.poll_type
+from poll_option inner join poll on poll.id = poll_option.poll_id
+where poll_vote.option_id = poll_option.id;
+
+-- votes that slipped through before would make creating the indexes fail,
+-- in that case the first vote is kept
+delete from poll_vote newer using poll_vote older
+where newer.option_id = older.option_id
+    and newer.ip_address = older.ip_address
+    and newer.id > older.id;
+delete from poll_vote newer using poll_vote older
+where newer.poll_type = 'single'
+    and newer.poll_id = older.poll_id
+    and newer.ip_address = older.ip_address
+    and newer.id > older.id;
+
+alter table poll_vote
+    alter column poll_id set not null,
+    alter column poll_type set not null,
+    drop constraint poll_vote_option_id_fkey,
+    add constraint poll_vote_option_id_poll_id_fkey foreign key (option_id, poll_id)
+        references poll_option(id, poll_id) on delete cascade,
+    add constraint poll_vote_poll_id_poll_type_fkey foreign key (poll_id, poll_type)
+        references poll(id, poll_type) on delete cascade on update cascade;
+
+-- an ip address can vote for an option only once
+create unique index unique_poll_vote_option_id_ip_address on poll_vote (option_id, ip_address);
+-- and only for one option, if the poll is a single poll
+create unique index unique_poll_vote_poll_id_ip_address_single on poll_vote (poll_id, ip_address)
+    where poll_type = 'single';
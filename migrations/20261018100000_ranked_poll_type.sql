@@ -0,0 +1,3 @@
+-- a new enum value can't be used in the transaction that added it,
+-- that's why the columns for ranked polls are added in the next migration
+alter type poll_type add value 'ranked';
@@ -0,0 +1,11 @@
+-- for ranked polls a ballot is stored as one vote per ranked option,
+-- the most preferred option has rank 1
+alter table poll_vote
+    add column rank integer,
+    add constraint check_rank_positive check (rank > 0),
+    add constraint check_rank_only_for_ranked_polls check ((poll_type = 'ranked') = (rank is not null));
+
+-- every rank can only be given once per ip address,
+-- which also means a second ballot can't be submitted
+create unique index unique_poll_vote_poll_id_ip_address_rank_ranked on poll_vote (poll_id, ip_address, rank)
+    where poll_type = 'ranked';
@@ -0,0 +1,2 @@
+-- see the ranked migrations, why this has to be separate
+alter type poll_type add value 'score';
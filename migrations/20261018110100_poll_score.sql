@@ -0,0 +1,13 @@
+-- voters of score polls rate every option with a score in this range
+alter table poll
+    add column min_score integer,
+    add column max_score integer,
+    add constraint check_score_range_only_for_score_polls
+        check ((poll_type = 'score') = (min_score is not null and max_score is not null)),
+    add constraint check_min_score_lower_than_max_score check (min_score < max_score);
+
+-- whether the score is in the range of the poll is checked by the program,
+-- since a check constraint can't look at another table
+alter table poll_vote
+    add column score integer,
+    add constraint check_score_only_for_score_polls check ((poll_type = 'score') = (score is not null));
@@ -0,0 +1,41 @@
+-- multiple polls can limit how many options a voter has to choose,
+-- the choices are then submitted all at once as a ballot
+alter table poll
+    add column min_choices integer,
+    add column max_choices integer,
+    add constraint check_choices_only_for_multiple_polls
+        check (poll_type = 'multiple' or (min_choices is null and max_choices is null)),
+    add constraint check_min_choices_positive check (min_choices > 0),
+    add constraint check_max_choices_higher_or_equal_than_min_choices check (max_choices >= min_choices);
+
+-- a ballot groups the votes an ip address submitted at once,
+-- there can only be one per ip address and poll
+create table poll_ballot
+(
+    id bigserial primary key,
+    poll_id bigint not null references poll(id) on delete cascade,
+    ip_address inet not null,
+    created_at timestamptz not null default now(),
+    constraint unique_poll_ballot_poll_id_ip_address unique (poll_id, ip_address),
+    -- target for the composite foreign key of poll_vote
+    constraint unique_poll_ballot_id_poll_id unique (id, poll_id)
+);
+
+alter table poll_vote
+    add column ballot_id bigint,
+    add constraint poll_vote_ballot_id_poll_id_fkey foreign key (ballot_id, poll_id)
+        references poll_ballot(id, poll_id) on delete cascade;
+
+-- ranked and score votes were already submitted as ballots, just without a row for them
+insert into poll_ballot (poll_id, ip_address, created_at)
+select poll_id, ip_address, min(created_at)
+from poll_vote
+where poll_type in ('ranked', 'score')
+group by poll_id, ip_address;
+
+update poll_vote
+set ballot_id = poll_ballot.id
+from poll_ballot
+where poll_vote.poll_id = poll_ballot.poll_id
+    and poll_vote.ip_address = poll_ballot.ip_address
+    and poll_vote.poll_type in ('ranked', 'score');
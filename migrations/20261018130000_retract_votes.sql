@@ -0,0 +1,21 @@
+-- votes and ballots are never deleted when a voter changes their mind,
+-- they are marked as retracted instead, so the timeline of votes stays intact
+alter table poll_ballot add column retracted_at timestamptz;
+alter table poll_vote add column retracted_at timestamptz;
+
+-- the uniqueness rules only apply to votes that still count
+alter table poll_ballot drop constraint unique_poll_ballot_poll_id_ip_address;
+create unique index unique_poll_ballot_poll_id_ip_address on poll_ballot (poll_id, ip_address)
+    where retracted_at is null;
+
+drop index unique_poll_vote_option_id_ip_address;
+create unique index unique_poll_vote_option_id_ip_address on poll_vote (option_id, ip_address)
+    where retracted_at is null;
+
+drop index unique_poll_vote_poll_id_ip_address_single;
+create unique index unique_poll_vote_poll_id_ip_address_single on poll_vote (poll_id, ip_address)
+    where poll_type = 'single' and retracted_at is null;
+
+drop index unique_poll_vote_poll_id_ip_address_rank_ranked;
+create unique index unique_poll_vote_poll_id_ip_address_rank_ranked on poll_vote (poll_id, ip_address, rank)
+    where poll_type = 'ranked' and retracted_at is null;
@@ -0,0 +1,4 @@
+-- the creator of a poll gets a secret token to manage it,
+-- only its sha256 hash is stored
+-- polls created before this migration have none and can't be managed
+alter table poll add column admin_token_hash bytea;
@@ -0,0 +1,8 @@
+-- polls can be scheduled to start accepting votes later than their creation
+alter table poll add column opens_at timestamptz;
+update poll set opens_at = created_at;
+alter table poll
+    alter column opens_at set not null,
+    alter column opens_at set default now(),
+    add constraint check_opens_at_higher_or_equal_than_created_at check (opens_at >= created_at),
+    add constraint check_timeout_at_higher_than_opens_at check (timeout_at > opens_at);
@@ -0,0 +1,9 @@
+-- full-text search over poll titles and option names,
+-- the simple configuration doesn't stem, since polls can be in any language
+alter table poll add column search_vector tsvector
+    generated always as (to_tsvector('simple', title)) stored;
+create index poll_search_vector_index on poll using gin (search_vector);
+
+alter table poll_option add column search_vector tsvector
+    generated always as (to_tsvector('simple', name)) stored;
+create index poll_option_search_vector_index on poll_option using gin (search_vector);
@@ -0,0 +1,8 @@
+-- polls are addressed by a random slug in all public routes,
+-- so they can't be enumerated through their sequential ids
+-- 16 random bytes as url safe base64 without padding, which are 22 characters
+alter table poll add column slug text not null unique
+    default translate(encode(uuid_send(gen_random_uuid()), 'base64'), '+/=', '-_');
+
+-- unlisted polls can only be found by knowing their slug
+alter table poll add column unlisted boolean not null default false;
@@ -0,0 +1,3 @@
+-- private polls can only be viewed and voted on with their access code
+-- stored as an argon2 hash in the PHC string format
+alter table poll add column access_code_hash text;
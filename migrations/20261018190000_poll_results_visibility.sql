@@ -0,0 +1,3 @@
+-- who can see the votes and results of a poll, the admin token always can
+create type results_visibility as enum ('always', 'after_voting', 'after_close');
+alter table poll add column results_visibility results_visibility not null default 'always';
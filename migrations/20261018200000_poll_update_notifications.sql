@@ -0,0 +1,29 @@
+-- every instance listens on the poll_updates channel to update the live results
+-- of its subscribers, the payload is the id of the poll that has changed
+-- notifications are only sent on commit and duplicates within
+-- a transaction are sent once, so a whole ballot results in one notification
+create function notify_poll_update() returns trigger as $$
+declare
+    poll_id bigint;
+begin
+    if tg_table_name = 'poll' and tg_op = 'DELETE' then
+        poll_id := old.id;
+    elsif tg_table_name = 'poll' then
+        poll_id := new.id;
+    elsif tg_op = 'DELETE' then
+        poll_id := old.poll_id;
+    else
+        poll_id := new.poll_id;
+    end if;
+    perform pg_notify('poll_updates', poll_id::text);
+    return null;
+end;
+$$ language plpgsql;
+
+-- votes are never deleted by themselves, only retracted
+create trigger poll_vote_notify_poll_update after insert or update on poll_vote
+    for each row execute function notify_poll_update();
+create trigger poll_option_notify_poll_update after insert or update or delete on poll_option
+    for each row execute function notify_poll_update();
+create trigger poll_notify_poll_update after update or delete on poll
+    for each row execute function notify_poll_update();
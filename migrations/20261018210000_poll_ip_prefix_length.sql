@@ -0,0 +1,8 @@
+-- votes store the network of the voter, every address within it counts as the same voter
+-- null uses the prefix lengths configured for the instance
+-- votes that were cast before keep their full address
+alter table poll
+    add column ipv4_prefix_length integer,
+    add column ipv6_prefix_length integer,
+    add constraint check_ipv4_prefix_length check (ipv4_prefix_length between 0 and 32),
+    add constraint check_ipv6_prefix_length check (ipv6_prefix_length between 0 and 128);
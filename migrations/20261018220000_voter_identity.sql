@@ -0,0 +1,30 @@
+-- how voters of a poll are told apart, by their ip address or by a signed cookie
+create type voter_identity as enum ('ip_address', 'cookie');
+alter table poll add column voter_identity voter_identity not null default 'ip_address';
+
+-- the voter a ballot or vote belongs to, prefixed with the kind of identity,
+-- e.g. ip:10.0.0.1/32 or cookie:<random id>
+-- the ip address is still stored with every vote, but only the voter is unique
+alter table poll_ballot add column voter text;
+update poll_ballot set voter = 'ip:' || ip_address::text;
+alter table poll_ballot alter column voter set not null;
+
+alter table poll_vote add column voter text;
+update poll_vote set voter = 'ip:' || ip_address::text;
+alter table poll_vote alter column voter set not null;
+
+drop index unique_poll_ballot_poll_id_ip_address;
+create unique index unique_poll_ballot_poll_id_voter on poll_ballot (poll_id, voter)
+    where retracted_at is null;
+
+drop index unique_poll_vote_option_id_ip_address;
+create unique index unique_poll_vote_option_id_voter on poll_vote (option_id, voter)
+    where retracted_at is null;
+
+drop index unique_poll_vote_poll_id_ip_address_single;
+create unique index unique_poll_vote_poll_id_voter_single on poll_vote (poll_id, voter)
+    where poll_type = 'single' and retracted_at is null;
+
+drop index unique_poll_vote_poll_id_ip_address_rank_ranked;
+create unique index unique_poll_vote_poll_id_voter_rank_ranked on poll_vote (poll_id, voter, rank)
+    where poll_type = 'ranked' and retracted_at is null;
@@ -0,0 +1,14 @@
+-- invite-only polls, voters are identified by a token the creator handed out
+alter type voter_identity add value 'token';
+
+create table voter_token (
+    id bigserial primary key,
+    poll_id bigint not null references poll (id) on delete cascade,
+    -- sha256 of the token, it's only returned once when minting
+    token_hash bytea not null unique,
+    created_at timestamptz not null default now(),
+    -- revoked tokens can't be used anymore and their votes don't count
+    revoked_at timestamptz
+);
+
+create index voter_token_poll_id on voter_token (poll_id);
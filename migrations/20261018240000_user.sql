@@ -0,0 +1,25 @@
+-- accounts, polls created while logged in are owned by the user
+create table "user" (
+    id bigserial primary key,
+    username text not null unique,
+    -- argon2 in the PHC string format
+    password_hash text not null,
+    created_at timestamptz not null default now()
+);
+
+create table user_session (
+    id bigserial primary key,
+    user_id bigint not null references "user" (id) on delete cascade,
+    -- sha256 of the session token, it's only returned once when logging in
+    token_hash bytea not null unique,
+    created_at timestamptz not null default now(),
+    -- the cleaner task removes expired sessions
+    expires_at timestamptz not null default now() + interval '30 days'
+);
+
+-- anonymous polls don't have an owner and are only managed with their admin token
+alter table poll add column owner_id bigint references "user" (id) on delete set null;
+create index poll_owner_id on poll (owner_id);
+
+-- voters are identified by their account
+alter type voter_identity add value 'user';
@@ -0,0 +1,4 @@
+-- options can swap their names, which only conflicts in between,
+-- so editing a poll defers this check until all options are renamed
+alter table poll_option drop constraint unique_name_poll_id,
+    add constraint unique_name_poll_id unique (name, poll_id) deferrable initially immediate;
@@ -0,0 +1,8 @@
+-- search headlines are html, so the titles and option names in them have to be escaped,
+-- the entities are their own tokens, so they are never highlighted
+create function escape_html(text) returns text
+language sql immutable strict parallel safe
+as $$
+    select replace(replace(replace(replace(replace($1,
+        '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '"', '&quot;'), '''', '&#39;')
+$$;
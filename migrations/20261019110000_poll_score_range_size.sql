@@ -0,0 +1,4 @@
+-- the results list how often every score of the range was given,
+-- so the range has to stay small, it has to match MAX_SCORES in the program
+alter table poll
+    add constraint check_score_range_size check (max_score::bigint - min_score < 100);
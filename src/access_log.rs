@@ -0,0 +1,82 @@
+use actix_web::{dev::ServiceRequest, web};
+
+// query parameters that carry secrets, they are accepted in the query, because
+// browsers can't send headers with websockets and server-sent events
+const REDACTED_QUERY_PARAMETERS: &[&str] = &["accessCode", "voterToken"];
+
+/// the request line like in the default log format,
+/// but the values of secret query parameters are replaced
+pub fn redacted_request_line(request: &ServiceRequest) -> String {
+    let query = request
+        .query_string()
+        .split('&')
+        .map(|parameter| {
+            // names can be percent-encoded, so they are compared decoded
+            let name = web::Query::<Vec<(String, String)>>::from_query(parameter)
+                .ok()
+                .and_then(|x| x.into_inner().into_iter().next())
+                .map(|(name, _)| name);
+            match name {
+                Some(name) if REDACTED_QUERY_PARAMETERS.contains(&name.as_str()) => {
+                    format!("{}=redacted", name)
+                }
+                _ => parameter.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    if query.is_empty() {
+        format!(
+            "{} {} {:?}",
+            request.method(),
+            request.path(),
+            request.version()
+        )
+    } else {
+        format!(
+            "{} {}?{} {:?}",
+            request.method(),
+            request.path(),
+            query,
+            request.version()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::redacted_request_line;
+
+    #[test]
+    fn redacts_secret_query_parameters() {
+        let request = TestRequest::get()
+            .uri("/polls/abc/votes/stream?accessCode=secret&limit=5&voterToken=secret")
+            .to_srv_request();
+
+        assert_eq!(
+            redacted_request_line(&request),
+            "GET /polls/abc/votes/stream?accessCode=redacted&limit=5&voterToken=redacted HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn redacts_percent_encoded_names() {
+        let request = TestRequest::get()
+            .uri("/polls/abc?access%43ode=secret")
+            .to_srv_request();
+
+        assert_eq!(
+            redacted_request_line(&request),
+            "GET /polls/abc?accessCode=redacted HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn keeps_requests_without_query() {
+        let request = TestRequest::get().uri("/polls").to_srv_request();
+
+        assert_eq!(redacted_request_line(&request), "GET /polls HTTP/1.1");
+    }
+}
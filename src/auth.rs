@@ -0,0 +1,72 @@
+use std::sync::OnceLock;
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// a random token, that is long enough to not be guessable
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+// the tokens are random and long, so a fast hash is enough,
+// unlike for passwords chosen by humans
+pub fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+// passwords and access codes are chosen by humans and can be short,
+// so they are hashed with a slow, salted hash
+// both functions take a while, so they should not run on the async runtime directly
+pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default()
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string())
+}
+
+/// a hash to verify against if there is none, e.g. for a username that doesn't exist,
+/// so it takes as long as verifying a wrong password
+pub fn dummy_password_hash() -> &'static str {
+    static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_PASSWORD_HASH
+        .get_or_init(|| hash_password("").expect("hashing with the default parameters can't fail"))
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    match PasswordHash::new(password_hash) {
+        Ok(password_hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &password_hash)
+            .is_ok(),
+        Err(e) => {
+            log::error!("{}", e);
+            false
+        }
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// the value followed by a signature, that only someone with the secret can create
+pub fn sign(secret: &[u8], value: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take a key of any size");
+    mac.update(value.as_bytes());
+    format!("{}.{}", value, hex::encode(mac.finalize().into_bytes()))
+}
+
+/// the value, if the signature created by sign is valid
+pub fn verify_signed<'a>(secret: &[u8], signed: &'a str) -> Option<&'a str> {
+    let (value, signature) = signed.rsplit_once('.')?;
+    let signature = hex::decode(signature).ok()?;
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take a key of any size");
+    mac.update(value.as_bytes());
+    // compares in constant time
+    mac.verify_slice(&signature).ok()?;
+    Some(value)
+}
@@ -1,5 +1,8 @@
 use std::time::Duration;
 
+use sqlx::postgres::PgListener;
+use tokio::sync::broadcast;
+
 async fn delete_old_polls(pool: &sqlx::PgPool) {
     let execute_result = sqlx::query!("delete from poll where delete_at <= now()")
         .execute(pool)
@@ -17,6 +20,23 @@ async fn delete_old_polls(pool: &sqlx::PgPool) {
     }
 }
 
+async fn delete_expired_sessions(pool: &sqlx::PgPool) {
+    let execute_result = sqlx::query!("delete from user_session where expires_at <= now()")
+        .execute(pool)
+        .await;
+    match execute_result {
+        Ok(query_result) => {
+            let rows_affected = query_result.rows_affected();
+            if rows_affected > 0 {
+                log::info!("deleted {} rows from table user_session", rows_affected);
+            }
+        }
+        Err(e) => {
+            log::error!("{}", e);
+        }
+    }
+}
+
 pub fn spawn_database_cleaner_task(pool: sqlx::PgPool) {
     actix_rt::spawn(async move {
         // every hour
@@ -26,6 +46,54 @@ pub fn spawn_database_cleaner_task(pool: sqlx::PgPool) {
         loop {
             interval.tick().await;
             delete_old_polls(&pool).await;
+            delete_expired_sessions(&pool).await;
+        }
+    });
+}
+
+// has to match the channel in the notify_poll_update trigger function
+const POLL_UPDATES_CHANNEL: &str = "poll_updates";
+
+/// sent instead of a poll id if updates might have been missed,
+/// poll ids start at 1, so this can't be the id of a poll
+pub const ALL_POLLS: i64 = 0;
+
+/// only returns on errors, e.g. if the connection could not be reestablished
+async fn forward_poll_updates(
+    pool: &sqlx::PgPool,
+    poll_updates: &broadcast::Sender<i64>,
+) -> sqlx::Result<()> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(POLL_UPDATES_CHANNEL).await?;
+    // updates might have been missed while this wasn't listening,
+    // when this is called the first time nobody is subscribed yet anyway
+    let _ = poll_updates.send(ALL_POLLS);
+    loop {
+        // None if the connection was lost, it's reestablished on the next call,
+        // but notifications in between are lost, so every poll might have changed
+        let Some(notification) = listener.try_recv().await? else {
+            log::warn!("lost the connection for poll updates, reconnecting");
+            let _ = poll_updates.send(ALL_POLLS);
+            continue;
+        };
+        match notification.payload().parse::<i64>() {
+            Ok(poll_id) => {
+                // this only fails if nobody is subscribed, which is fine
+                let _ = poll_updates.send(poll_id);
+            }
+            Err(e) => log::error!("invalid poll update {:?}: {}", notification.payload(), e),
+        }
+    }
+}
+
+// votes can be cast on any instance, so changes are published through the database
+pub fn spawn_poll_update_listener_task(pool: sqlx::PgPool, poll_updates: broadcast::Sender<i64>) {
+    actix_rt::spawn(async move {
+        loop {
+            if let Err(e) = forward_poll_updates(&pool, &poll_updates).await {
+                log::error!("{}", e);
+            }
+            actix_rt::time::sleep(Duration::from_secs(5)).await;
         }
     });
 }
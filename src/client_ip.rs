@@ -0,0 +1,279 @@
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::HttpRequest;
+use sqlx::types::ipnetwork::IpNetwork;
+
+/// the forwarding header the trusted proxies set, only this one is used,
+/// since proxies usually pass the other ones from the client on unchanged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientIpHeader {
+    Forwarded,
+    XForwardedFor,
+    XRealIp,
+}
+
+impl std::str::FromStr for ClientIpHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "forwarded" => Ok(Self::Forwarded),
+            "x-forwarded-for" => Ok(Self::XForwardedFor),
+            "x-real-ip" => Ok(Self::XRealIp),
+            _ => Err(format!("unsupported header {:?}", s)),
+        }
+    }
+}
+
+/// the address of the client that sent the request, if the peer is a trusted proxy
+/// the address is taken from the configured header, otherwise the headers are ignored,
+/// since anyone could set them
+pub fn client_ip_address(
+    request: &HttpRequest,
+    trusted_proxies: &[IpNetwork],
+    header: ClientIpHeader,
+) -> Option<IpAddr> {
+    let peer = request.peer_addr()?.ip();
+    if !is_trusted(peer, trusted_proxies) {
+        return Some(peer);
+    }
+    let forwarded_for = forwarded_for(request, header);
+    // every proxy appends the address it received the request from,
+    // so the client is the rightmost address that is not a trusted proxy
+    // everything left of it could have been sent by the client itself
+    let mut client = peer;
+    for address in forwarded_for.iter().rev() {
+        let Some(address) = address else {
+            // unknown or obfuscated, the proxy that added it is the best guess
+            break;
+        };
+        client = *address;
+        if !is_trusted(client, trusted_proxies) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+fn is_trusted(address: IpAddr, trusted_proxies: &[IpNetwork]) -> bool {
+    // ipv4 addresses can also arrive mapped into ipv6
+    let address = match address {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(address),
+        IpAddr::V4(_) => address,
+    };
+    trusted_proxies.iter().any(|x| x.contains(address))
+}
+
+/// the addresses of the header, from the client to the last proxy,
+/// None for addresses that could not be parsed
+fn forwarded_for(request: &HttpRequest, header: ClientIpHeader) -> Vec<Option<IpAddr>> {
+    let name = match header {
+        ClientIpHeader::Forwarded => "Forwarded",
+        ClientIpHeader::XForwardedFor => "X-Forwarded-For",
+        ClientIpHeader::XRealIp => "X-Real-IP",
+    };
+    let values = request
+        .headers()
+        .get_all(name)
+        .filter_map(|x| x.to_str().ok());
+    match header {
+        // e.g. Forwarded: for=192.0.2.43, for="[2001:db8:cafe::17]:4711";proto=https
+        ClientIpHeader::Forwarded => values
+            .flat_map(|x| x.split(','))
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.trim().split_once('=')?;
+                    key.eq_ignore_ascii_case("for")
+                        .then(|| value.trim_matches('"'))
+                })
+            })
+            .map(parse_address)
+            .collect(),
+        ClientIpHeader::XForwardedFor | ClientIpHeader::XRealIp => values
+            .flat_map(|x| x.split(','))
+            .map(parse_address)
+            .collect(),
+    }
+}
+
+/// an address with an optional port, ipv6 addresses with a port are in brackets
+fn parse_address(address: &str) -> Option<IpAddr> {
+    let address = address.trim();
+    address
+        .parse::<IpAddr>()
+        .ok()
+        .or_else(|| address.parse::<SocketAddr>().ok().map(|x| x.ip()))
+        .or_else(|| {
+            address
+                .strip_prefix('[')
+                .and_then(|x| x.strip_suffix(']'))
+                .and_then(|x| x.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use actix_web::test::TestRequest;
+    use sqlx::types::ipnetwork::IpNetwork;
+
+    use super::{client_ip_address, parse_address, ClientIpHeader};
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn trusted_proxies() -> Vec<IpNetwork> {
+        vec!["10.0.0.0/8".parse().unwrap(), "::1".parse().unwrap()]
+    }
+
+    fn request_from(peer: &str) -> TestRequest {
+        TestRequest::default().peer_addr(SocketAddr::new(ip(peer), 4711))
+    }
+
+    #[test]
+    fn ignores_headers_of_untrusted_peers() {
+        let request = request_from("203.0.113.5")
+            .insert_header(("X-Forwarded-For", "6.6.6.6"))
+            .insert_header(("Forwarded", "for=6.6.6.6"))
+            .insert_header(("X-Real-IP", "6.6.6.6"))
+            .to_http_request();
+
+        for header in [
+            ClientIpHeader::Forwarded,
+            ClientIpHeader::XForwardedFor,
+            ClientIpHeader::XRealIp,
+        ] {
+            assert_eq!(
+                client_ip_address(&request, &trusted_proxies(), header),
+                Some(ip("203.0.113.5"))
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_headers_other_than_the_configured_one() {
+        let request = request_from("10.0.0.1")
+            .insert_header(("Forwarded", "for=6.6.6.6"))
+            .insert_header(("X-Real-IP", "6.6.6.7"))
+            .insert_header(("X-Forwarded-For", "203.0.113.5"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip_address(&request, &trusted_proxies(), ClientIpHeader::XForwardedFor),
+            Some(ip("203.0.113.5"))
+        );
+    }
+
+    #[test]
+    fn uses_the_peer_if_the_configured_header_is_missing() {
+        let request = request_from("10.0.0.1")
+            .insert_header(("Forwarded", "for=6.6.6.6"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip_address(&request, &trusted_proxies(), ClientIpHeader::XForwardedFor),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    #[test]
+    fn takes_the_rightmost_untrusted_address_of_a_chain() {
+        // the client sent the first address itself
+        let request = request_from("10.0.0.1")
+            .insert_header(("X-Forwarded-For", "6.6.6.6, 203.0.113.5, 10.0.0.2"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip_address(&request, &trusted_proxies(), ClientIpHeader::XForwardedFor),
+            Some(ip("203.0.113.5"))
+        );
+    }
+
+    #[test]
+    fn combines_repeated_headers() {
+        let request = request_from("10.0.0.1")
+            .append_header(("Forwarded", "for=6.6.6.6"))
+            .append_header(("Forwarded", "for=203.0.113.5;proto=https, for=10.0.0.2"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip_address(&request, &trusted_proxies(), ClientIpHeader::Forwarded),
+            Some(ip("203.0.113.5"))
+        );
+    }
+
+    #[test]
+    fn stops_at_unknown_addresses() {
+        let request = request_from("10.0.0.1")
+            .insert_header(("Forwarded", "for=6.6.6.6, for=unknown, for=10.0.0.2"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip_address(&request, &trusted_proxies(), ClientIpHeader::Forwarded),
+            Some(ip("10.0.0.2"))
+        );
+    }
+
+    #[test]
+    fn uses_the_last_proxy_if_all_addresses_are_trusted() {
+        let request = request_from("10.0.0.1")
+            .insert_header(("X-Forwarded-For", "10.0.0.3, 10.0.0.2"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip_address(&request, &trusted_proxies(), ClientIpHeader::XForwardedFor),
+            Some(ip("10.0.0.3"))
+        );
+    }
+
+    #[test]
+    fn trusts_ipv4_mapped_proxies() {
+        let request = request_from("::ffff:10.0.0.1")
+            .insert_header(("X-Real-IP", "203.0.113.5"))
+            .to_http_request();
+
+        assert_eq!(
+            client_ip_address(&request, &trusted_proxies(), ClientIpHeader::XRealIp),
+            Some(ip("203.0.113.5"))
+        );
+    }
+
+    #[test]
+    fn parses_addresses_with_and_without_ports() {
+        assert_eq!(parse_address(" 192.0.2.43 "), Some(ip("192.0.2.43")));
+        assert_eq!(parse_address("192.0.2.43:4711"), Some(ip("192.0.2.43")));
+        assert_eq!(
+            parse_address("2001:db8:cafe::17"),
+            Some(ip("2001:db8:cafe::17"))
+        );
+        assert_eq!(
+            parse_address("[2001:db8:cafe::17]"),
+            Some(ip("2001:db8:cafe::17"))
+        );
+        assert_eq!(
+            parse_address("[2001:db8:cafe::17]:4711"),
+            Some(ip("2001:db8:cafe::17"))
+        );
+        assert_eq!(
+            parse_address("::ffff:192.0.2.43"),
+            Some(ip("::ffff:192.0.2.43"))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_and_obfuscated_addresses() {
+        assert_eq!(parse_address("unknown"), None);
+        assert_eq!(parse_address("_hidden"), None);
+        assert_eq!(parse_address(""), None);
+    }
+
+    #[test]
+    fn parses_header_names_case_insensitively() {
+        assert_eq!("x-forwarded-for".parse(), Ok(ClientIpHeader::XForwardedFor));
+        assert_eq!("Forwarded".parse(), Ok(ClientIpHeader::Forwarded));
+        assert_eq!("X-Real-IP".parse(), Ok(ClientIpHeader::XRealIp));
+        assert!("X-Client-IP".parse::<ClientIpHeader>().is_err());
+    }
+}
@@ -1,21 +1,49 @@
 use actix_cors::Cors;
 use actix_web::{middleware, web, App, HttpServer, Scope};
+use sqlx::types::ipnetwork::IpNetwork;
+use tokio::sync::broadcast;
 
-use crate::{background_tasks::spawn_database_cleaner_task, routes::get_api_index};
+use crate::{
+    access_log::redacted_request_line,
+    background_tasks::{spawn_database_cleaner_task, spawn_poll_update_listener_task},
+    client_ip::ClientIpHeader,
+    routes::get_api_index,
+};
 
+mod access_log;
+mod auth;
+mod client_ip;
 mod models;
 mod routes;
+mod tally;
 
 mod background_tasks;
 
 struct AppData {
     pool: sqlx::PgPool,
+    // ids of polls whose votes have changed, for live results
+    // published by the listener task for the changes of every instance
+    poll_updates: broadcast::Sender<i64>,
+    // proxies whose forwarding headers are used to determine the client address
+    trusted_proxies: Vec<IpNetwork>,
+    // the one forwarding header those proxies set
+    client_ip_header: ClientIpHeader,
+    // addresses within networks of these prefix lengths count as the same voter,
+    // unless the poll sets its own
+    ipv4_prefix_length: u8,
+    ipv6_prefix_length: u8,
+    // signs the cookies of voters, has to be the same for all instances
+    cookie_secret: Vec<u8>,
+    // otherwise creating polls requires logging in
+    allow_anonymous_polls: bool,
 }
 
 #[actix_web::main]
 async fn main() {
     dotenvy::dotenv().expect("Failed to load .env file");
 
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
     let database_url = dotenvy::var("DATABASE_URL").expect("DATABASE_URL must be set");
 
     let pg_pool = sqlx::PgPool::connect(&database_url)
@@ -30,29 +58,92 @@ async fn main() {
         .parse::<u16>()
         .expect("Could not parse PORT");
 
+    // comma separated addresses or networks, e.g. 127.0.0.1,10.0.0.0/8
+    let trusted_proxies = dotenvy::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.parse::<IpNetwork>())
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Could not parse TRUSTED_PROXIES");
+    // the other forwarding headers are ignored, since proxies usually pass them on
+    // from the client unchanged, which would let clients choose their address
+    let client_ip_header = dotenvy::var("CLIENT_IP_HEADER")
+        .unwrap_or("X-Forwarded-For".to_string())
+        .parse::<ClientIpHeader>()
+        .expect("Could not parse CLIENT_IP_HEADER");
+    // a single ipv6 client usually has a whole /64 or /56 available
+    let ipv4_prefix_length = dotenvy::var("IPV4_PREFIX_LENGTH")
+        .unwrap_or("32".to_string())
+        .parse::<u8>()
+        .ok()
+        .filter(|x| *x <= 32)
+        .expect("Could not parse IPV4_PREFIX_LENGTH");
+    let ipv6_prefix_length = dotenvy::var("IPV6_PREFIX_LENGTH")
+        .unwrap_or("64".to_string())
+        .parse::<u8>()
+        .ok()
+        .filter(|x| *x <= 128)
+        .expect("Could not parse IPV6_PREFIX_LENGTH");
+    let cookie_secret = match dotenvy::var("COOKIE_SECRET") {
+        Ok(cookie_secret) => cookie_secret.into_bytes(),
+        Err(_) => {
+            log::warn!("COOKIE_SECRET is not set, voter cookies will be invalid after a restart");
+            auth::generate_token().into_bytes()
+        }
+    };
+    let allow_anonymous_polls = dotenvy::var("ALLOW_ANONYMOUS_POLLS")
+        .unwrap_or("true".to_string())
+        .parse::<bool>()
+        .expect("Could not parse ALLOW_ANONYMOUS_POLLS");
+
     let api_prefix = "/";
 
-    let app_data = web::Data::new(AppData { pool: pg_pool });
+    // subscribers that fall behind by this many updates
+    // skip the missed ones and just query the current counts
+    let (poll_updates, _) = broadcast::channel(1024);
+    spawn_poll_update_listener_task(pg_pool.clone(), poll_updates.clone());
+    let app_data = web::Data::new(AppData {
+        pool: pg_pool,
+        poll_updates,
+        trusted_proxies,
+        client_ip_header,
+        ipv4_prefix_length,
+        ipv6_prefix_length,
+        cookie_secret,
+        allow_anonymous_polls,
+    });
 
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    // hashed now, so the first login with an unknown username isn't slower than the others
+    auth::dummy_password_hash();
 
     println!("Listening on {}:{}", bind_address, port);
     HttpServer::new(move || {
         let polls_scope =
             Scope::new(&format!("{}polls", api_prefix)).configure(routes::poll::configure_routes);
-        let options_scope = Scope::new(&format!("{}poll-options", api_prefix))
-            .configure(routes::option::configure_routes);
+        let users_scope =
+            Scope::new(&format!("{}users", api_prefix)).configure(routes::user::configure_routes);
+        let me_scope =
+            Scope::new(&format!("{}me", api_prefix)).configure(routes::user::configure_me_routes);
 
         App::new()
             .app_data(app_data.clone())
             .wrap(middleware::NormalizePath::new(
                 middleware::TrailingSlash::Trim,
             ))
-            .wrap(middleware::Logger::default())
+            // the default format, except for the redacted request line
+            .wrap(
+                middleware::Logger::new(
+                    r#"%a "%{request_line}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#,
+                )
+                .custom_request_replace("request_line", redacted_request_line),
+            )
             .wrap(Cors::permissive())
             .route(api_prefix, web::get().to(get_api_index))
             .service(polls_scope)
-            .service(options_scope)
+            .service(users_scope)
+            .service(me_scope)
     })
     .bind((bind_address, port))
     .unwrap()
@@ -7,17 +7,88 @@ use sqlx::types::ipnetwork::IpNetwork;
 pub enum PollType {
     Single,
     Multiple,
+    // voters submit an ordered list of options as a ballot
+    Ranked,
+    // voters rate every option with a score as a ballot
+    Score,
+}
+
+#[derive(Debug, Default, sqlx::Type, Serialize, Deserialize, PartialEq)]
+#[sqlx(type_name = "results_visibility", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum ResultsVisibility {
+    #[default]
+    Always,
+    // only to those who have voted in the poll
+    AfterVoting,
+    // only once timeout_at has passed
+    AfterClose,
+}
+
+#[derive(Debug, Default, sqlx::Type, Serialize, Deserialize, PartialEq)]
+#[sqlx(type_name = "voter_identity", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum VoterIdentity {
+    // every address within the same network is one voter
+    #[default]
+    IpAddress,
+    // a signed cookie with a random id, for voters that share an address
+    Cookie,
+    // invite-only, every token minted by the creator of the poll is one voter
+    Token,
+    // voters have to be logged in, every account is one voter
+    User,
 }
 
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Poll {
+    // only used internally, polls are addressed by their slug
+    #[serde(skip_serializing)]
     pub id: i64,
+    pub slug: String,
     pub title: String,
     pub poll_type: PollType,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub opens_at: chrono::DateTime<chrono::Utc>,
     pub timeout_at: chrono::DateTime<chrono::Utc>,
     pub delete_at: chrono::DateTime<chrono::Utc>,
+    // only set for score polls
+    pub min_score: Option<i32>,
+    pub max_score: Option<i32>,
+    // only set for multiple polls, which only accept ballots then
+    pub min_choices: Option<i32>,
+    pub max_choices: Option<i32>,
+    // not listed, but accessible by anyone who knows the slug
+    pub unlisted: bool,
+    pub results_visibility: ResultsVisibility,
+    pub voter_identity: VoterIdentity,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PollStatus {
+    // opens_at has not been reached yet
+    Scheduled,
+    Open,
+    Closed,
+    // delete_at has passed, but the cleaner task has not removed the poll yet
+    PendingDeletion,
+}
+
+impl Poll {
+    pub fn status(&self) -> PollStatus {
+        let now = chrono::Utc::now();
+        if self.delete_at <= now {
+            PollStatus::PendingDeletion
+        } else if self.timeout_at <= now {
+            PollStatus::Closed
+        } else if self.opens_at > now {
+            PollStatus::Scheduled
+        } else {
+            PollStatus::Open
+        }
+    }
 }
 
 // basically three models
@@ -26,13 +97,15 @@ pub struct Poll {
 // votes
 
 // do we need a creator?
+// yes, polls created while logged in are owned by that user,
+// anonymous polls are still possible, unless the instance disallows them
 // poll:
 //      name,
 //      type of vote (multiple or one vote),
 //      timestamp of creation,
 //      how long voting is allowed
 //      and when to delete (let's say it can't be bigger than a week and creator can make it shorter)
-//      reference to creator? no let's make it simple and just use the ip address
+//      reference to creator, the owner, if it was not created anonymously
 
 // options:
 //      name,
@@ -42,7 +115,6 @@ pub struct Poll {
 pub struct PollOption {
     pub id: i64,
     pub name: String,
-    pub poll_id: i64,
 }
 
 #[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
@@ -51,6 +123,44 @@ pub struct PollVote {
     pub option_id: i64,
     pub ip_address: IpNetwork,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    // only set for ranked polls
+    pub rank: Option<i32>,
+    // only set for score polls
+    pub score: Option<i32>,
+    // only set if the vote was submitted as part of a ballot
+    pub ballot_id: Option<i64>,
+    // retracted votes are kept, but don't count anymore
+    pub retracted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// all votes an ip address submitted at once
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PollBallot {
+    pub id: i64,
+    pub ip_address: IpNetwork,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub retracted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// the password hash is never returned, so it's not part of the model
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// the token itself is only returned once, when it's minted
+#[derive(Debug, sqlx::FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoterToken {
+    pub id: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    // when the ballot of the token was cast, votes retracted by revoking it don't count
+    pub redeemed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Debug)]
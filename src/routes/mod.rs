@@ -1,6 +1,9 @@
 use std::sync::OnceLock;
 
-use actix_web::{HttpRequest, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use tokio::sync::Semaphore;
+
+use crate::{auth, models::Message};
 
 macro_rules! unwrap_or_log_and_internal_server_error_response {
     ($result:expr, $message:expr) => {
@@ -18,12 +21,130 @@ macro_rules! unwrap_or_log_and_internal_server_error_response {
 
 pub mod option;
 pub mod poll;
+pub mod user;
+mod voter;
+pub mod voter_token;
+
+/// polls are addressed by their slug in routes, but by their id internally
+async fn retrieve_poll_id(pool: &sqlx::PgPool, slug: &str) -> Result<i64, HttpResponse> {
+    let poll_result = sqlx::query!(r#"select id from poll where slug = $1"#, slug)
+        .fetch_optional(pool)
+        .await;
+    match poll_result {
+        Ok(Some(poll)) => Ok(poll.id),
+        Ok(None) => Err(HttpResponse::NotFound().json(Message("no such poll"))),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(HttpResponse::InternalServerError().json(Message("internal server error")))
+        }
+    }
+}
+
+const ACCESS_CODE_HEADER: &str = "X-Access-Code";
+// longer codes are rejected before hashing, since the cost grows with the length
+const MAX_ACCESS_CODE_LENGTH: usize = 128;
+
+// argon2 needs about 19 MiB of memory and takes a while, anyone can make the server
+// hash a password or verify an access code, so only a few of these run at once
+// and the others wait
+static PASSWORD_HASHING: Semaphore = Semaphore::const_new(4);
+
+/// runs f on the blocking thread pool, limited by PASSWORD_HASHING
+async fn limit_password_hashing<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, HttpResponse> {
+    let permit = match PASSWORD_HASHING.acquire().await {
+        Ok(permit) => permit,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(HttpResponse::InternalServerError().json(Message("internal server error")));
+        }
+    };
+    let result = web::block(f).await;
+    drop(permit);
+    result.map_err(|e| {
+        log::error!("{}", e);
+        HttpResponse::InternalServerError().json(Message("internal server error"))
+    })
+}
+
+/// auth::verify_password, limited by PASSWORD_HASHING
+async fn verify_password(password: String, password_hash: String) -> Result<bool, HttpResponse> {
+    limit_password_hashing(move || auth::verify_password(&password, &password_hash)).await
+}
+
+/// auth::hash_password, limited by PASSWORD_HASHING
+async fn hash_password(password: String) -> Result<String, HttpResponse> {
+    limit_password_hashing(move || auth::hash_password(&password))
+        .await?
+        .map_err(|e| {
+            log::error!("{}", e);
+            HttpResponse::InternalServerError().json(Message("internal server error"))
+        })
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessCodeQuery {
+    access_code: Option<String>,
+}
+
+/// like retrieve_poll_id, but private polls additionally require their access code,
+/// either in the X-Access-Code header or in the accessCode query parameter
+/// the code is verified on every request, including every connect of a stream or socket
+async fn authorize_poll_access(
+    pool: &sqlx::PgPool,
+    slug: &str,
+    request: &HttpRequest,
+) -> Result<i64, HttpResponse> {
+    let poll_result = sqlx::query!(
+        r#"select id, access_code_hash from poll where slug = $1"#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await;
+    let poll = match poll_result {
+        Ok(Some(poll)) => poll,
+        Ok(None) => return Err(HttpResponse::NotFound().json(Message("no such poll"))),
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(HttpResponse::InternalServerError().json(Message("internal server error")));
+        }
+    };
+    let Some(access_code_hash) = poll.access_code_hash else {
+        return Ok(poll.id);
+    };
+
+    let header_access_code = request
+        .headers()
+        .get(ACCESS_CODE_HEADER)
+        .and_then(|x| x.to_str().ok())
+        .map(|x| x.to_string());
+    let access_code = header_access_code.or_else(|| {
+        web::Query::<AccessCodeQuery>::from_query(request.query_string())
+            .ok()
+            .and_then(|x| x.into_inner().access_code)
+    });
+    let Some(access_code) = access_code else {
+        return Err(HttpResponse::Unauthorized().json(Message("poll requires an access code")));
+    };
+    // no access code is that long, so there is no need to hash it
+    if access_code.chars().count() > MAX_ACCESS_CODE_LENGTH {
+        return Err(HttpResponse::Unauthorized().json(Message("invalid access code")));
+    }
+    if verify_password(access_code, access_code_hash).await? {
+        Ok(poll.id)
+    } else {
+        Err(HttpResponse::Unauthorized().json(Message("invalid access code")))
+    }
+}
 
 #[derive(serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ApiIndexResponseData {
     polls: String,
-    poll_options: String,
+    users: String,
+    me: String,
 }
 
 static ENDPOINTS: OnceLock<ApiIndexResponseData> = OnceLock::new();
@@ -34,7 +155,8 @@ pub async fn get_api_index(request: HttpRequest) -> HttpResponse {
         let origin = format!("http://{}:{}/", "127.0.0.1", port);
         ApiIndexResponseData {
             polls: format!("{}polls", origin),
-            poll_options: format!("{}poll-options", origin),
+            users: format!("{}users", origin),
+            me: format!("{}me", origin),
         }
     });
     HttpResponse::Ok().json(endpoints)
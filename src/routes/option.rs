@@ -2,20 +2,29 @@ use actix_web::{
     web::{self, ServiceConfig},
     HttpRequest, HttpResponse, Responder,
 };
-use sqlx::types::ipnetwork::IpNetwork;
 
+use super::{authorize_poll_access, voter::Voter};
 use crate::{
     models::{self, Message},
     AppData,
 };
 
-async fn get_option(app_data: web::Data<AppData>, path_id: web::Path<i64>) -> impl Responder {
-    let id = path_id.into_inner();
+async fn get_option(
+    app_data: web::Data<AppData>,
+    path: web::Path<(String, i64)>,
+    request: HttpRequest,
+) -> impl Responder {
+    let (slug, id) = path.into_inner();
     let pool = &app_data.pool;
+    let poll_id = match authorize_poll_access(pool, &slug, &request).await {
+        Ok(poll_id) => poll_id,
+        Err(response) => return response,
+    };
     let poll_option_result = sqlx::query_as!(
         models::PollOption,
-        r#"select id, poll_id, name from poll_option where id = $1"#,
-        &id as &i64
+        r#"select id, name from poll_option where id = $1 and poll_id = $2"#,
+        &id as &i64,
+        &poll_id as &i64
     )
     .fetch_one(pool)
     .await;
@@ -34,91 +43,239 @@ async fn get_option(app_data: web::Data<AppData>, path_id: web::Path<i64>) -> im
 
 async fn post_vote(
     app_data: web::Data<AppData>,
-    path_id: web::Path<i64>,
+    path: web::Path<(String, i64)>,
     request: HttpRequest,
 ) -> impl Responder {
-    let id = path_id.into_inner();
+    let (slug, id) = path.into_inner();
     let pool = &app_data.pool;
-    let ip_address: IpNetwork = match request.peer_addr() {
-        Some(addr) => addr.ip().into(),
-        None => {
-            log::error!("peer_addr is None");
-            return HttpResponse::InternalServerError().json(Message("internal server error"));
-        }
+    let poll_id = match authorize_poll_access(pool, &slug, &request).await {
+        Ok(poll_id) => poll_id,
+        Err(response) => return response,
+    };
+    let voter = match Voter::resolve(pool, poll_id, &request).await {
+        Ok(voter) => voter,
+        Err(response) => return response,
     };
+    voter.respond(store_vote(pool, poll_id, id, &voter).await)
+}
 
-    // first make sure option exists
-    let poll_option_id_result =
-        sqlx::query!(r#"select id from poll_option where id = $1"#, &id as &i64)
-            .fetch_optional(pool)
-            .await;
-    let poll_option = unwrap_or_log_and_internal_server_error_response!(
-        poll_option_id_result,
-        "internal server error"
-    );
-    if poll_option.is_none() {
-        return HttpResponse::BadRequest().json(Message("no such poll option"));
+/// also used for votes cast over a websocket
+pub(super) async fn store_vote(
+    pool: &sqlx::PgPool,
+    poll_id: i64,
+    id: i64,
+    voter: &Voter,
+) -> HttpResponse {
+    // first make sure option exists and belongs to the poll
+    let poll_result = sqlx::query!(
+        r#"select poll.id, poll_type as "poll_type!: models::PollType", opens_at, timeout_at, min_choices
+        from poll inner join poll_option on poll.id = poll_option.poll_id
+        where poll_option.id = $1 and poll.id = $2"#,
+        &id as &i64,
+        &poll_id as &i64
+    )
+    .fetch_optional(pool)
+    .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+    let poll = match poll {
+        Some(poll) => poll,
+        None => return HttpResponse::BadRequest().json(Message("no such poll option")),
+    };
+    if poll.opens_at > chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is not open yet"));
+    }
+    // the cleaner task only removes polls after delete_at,
+    // so closed polls still exist here
+    if poll.timeout_at <= chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is closed"));
+    }
+    // a single vote can't express a ranking or a score,
+    // choice limits can only be checked for all choices at once
+    // and a voter token casts exactly one ballot, not one vote after another
+    if matches!(
+        poll.poll_type,
+        models::PollType::Ranked | models::PollType::Score
+    ) || poll.min_choices.is_some()
+        || !voter.may_change_ballot()
+    {
+        return HttpResponse::BadRequest().json(Message("poll only accepts ballots"));
     }
 
-    // get all votes for this ip address and the specified poll_option
-    let casted_votes_of_ip_result = sqlx::query_as!(
+    // the unique indexes on poll_vote decide whether the vote is allowed,
+    // so concurrent requests of the same voter can't both succeed
+    // for single polls every vote of the voter in the whole poll counts,
+    // for multiple polls only a vote for the same option
+    let vote_result = sqlx::query_as!(
         models::PollVote,
-        r#"select id, option_id, ip_address, created_at from poll_vote where ip_address = $1 and option_id = $2"#,
-        &ip_address,
-        &id,
+        r#"insert into poll_vote (option_id, poll_id, poll_type, ip_address, voter)
+        values ($1, $2, $3, $4, $5)
+        on conflict do nothing
+        returning id, option_id, ip_address, created_at, rank, score, ballot_id, retracted_at"#,
+        &id as &i64,
+        &poll.id as &i64,
+        &poll.poll_type as &models::PollType,
+        &voter.ip_address,
+        &voter.id,
     )
-    .fetch_all(pool)
+    .fetch_optional(pool)
     .await;
-
-    let casted_votes_of_ip = unwrap_or_log_and_internal_server_error_response!(
-        casted_votes_of_ip_result,
-        "internal server error"
-    );
-
-    // if there are no votes of the ip address for this option
-    // then no further option is allowed and we can proceed
-    if !casted_votes_of_ip.is_empty() {
-        // then check if ip already voted for this option
-        if casted_votes_of_ip.iter().any(|item| item.option_id == id) {
-            return HttpResponse::BadRequest()
-                .json(Message("you have already voted for this option"));
-        }
-
-        // now let's check if the poll allows multiple votes
-        let poll_result = sqlx::query!(
-            r#"select poll_type as "poll_type!: models::PollType"
-            from poll inner join poll_option on poll.id = poll_option.poll_id
-            where poll_option.id = $1"#,
-            &id as &i64
-        )
-        .fetch_one(pool)
-        .await;
-        let poll =
-            unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
-        if poll.poll_type == models::PollType::Single {
-            return HttpResponse::BadRequest().json(Message("poll does not allow multiple votes"));
-        }
+    let vote =
+        unwrap_or_log_and_internal_server_error_response!(vote_result, "internal server error");
+    if let Some(vote) = vote {
+        return HttpResponse::Ok().json(vote);
     }
 
-    let vote_result = sqlx::query_as!(
-        models::PollVote,
-        r#"insert into poll_vote (option_id, ip_address) values ($1, $2) returning *"#,
+    // nothing was inserted, so find out why to give a helpful message
+    let voted_for_option_result = sqlx::query!(
+        r#"select exists(select 1 from poll_vote
+        where option_id = $1 and voter = $2 and retracted_at is null) as "voted!""#,
         &id as &i64,
-        ip_address
+        &voter.id,
     )
     .fetch_one(pool)
     .await;
-    match vote_result {
-        Ok(vote) => HttpResponse::Ok().json(vote),
-        Err(e) => {
-            log::error!("{}", e);
-            HttpResponse::InternalServerError().json(Message("internal server error"))
-        }
+    let voted_for_option = unwrap_or_log_and_internal_server_error_response!(
+        voted_for_option_result,
+        "internal server error"
+    );
+    if voted_for_option.voted {
+        HttpResponse::BadRequest().json(Message("you have already voted for this option"))
+    } else {
+        HttpResponse::BadRequest().json(Message("poll does not allow multiple votes"))
     }
 }
 
+/// registered below the slug of the poll the options belong to
 pub fn configure_routes(config: &mut ServiceConfig) {
     config.route("/{id}", web::get().to(get_option));
     // only post for votes
     config.route("/{id}/votes", web::post().to(post_vote));
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use futures_util::future::join_all;
+
+    use super::{super::voter::Voter, store_vote};
+    use crate::models;
+
+    /// returns the id of the poll and the ids of its two options
+    async fn create_poll(pool: &sqlx::PgPool, poll_type: models::PollType) -> (i64, i64, i64) {
+        let poll = sqlx::query!(
+            r#"insert into poll (title, poll_type) values ('test', $1) returning id"#,
+            poll_type as models::PollType
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        let mut option_ids: Vec<i64> = sqlx::query_scalar!(
+            r#"insert into poll_option (name, poll_id) values ('a', $1), ('b', $1) returning id"#,
+            poll.id
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        option_ids.sort();
+        (poll.id, option_ids[0], option_ids[1])
+    }
+
+    const CONCURRENT_VOTES: usize = 50;
+
+    fn voter() -> Voter {
+        Voter::from_ip_address("127.0.0.1/32".parse().unwrap())
+    }
+
+    async fn count_votes(pool: &sqlx::PgPool, poll_id: i64) -> i64 {
+        sqlx::query_scalar!(
+            r#"select count(*) as "count!" from poll_vote where poll_id = $1"#,
+            poll_id
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn single_poll_allows_one_vote_in_the_whole_poll(pool: sqlx::PgPool) {
+        let (poll_id, option_a, option_b) = create_poll(&pool, models::PollType::Single).await;
+
+        let response = store_vote(&pool, poll_id, option_a, &voter()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = store_vote(&pool, poll_id, option_b, &voter()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = store_vote(&pool, poll_id, option_a, &voter()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(count_votes(&pool, poll_id).await, 1);
+    }
+
+    #[sqlx::test]
+    async fn multiple_poll_allows_one_vote_per_option(pool: sqlx::PgPool) {
+        let (poll_id, option_a, option_b) = create_poll(&pool, models::PollType::Multiple).await;
+
+        let response = store_vote(&pool, poll_id, option_a, &voter()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = store_vote(&pool, poll_id, option_b, &voter()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = store_vote(&pool, poll_id, option_a, &voter()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(count_votes(&pool, poll_id).await, 2);
+    }
+
+    #[sqlx::test]
+    async fn other_voters_are_not_affected(pool: sqlx::PgPool) {
+        let (poll_id, option_a, _) = create_poll(&pool, models::PollType::Single).await;
+        let other_voter = Voter::from_ip_address("127.0.0.2/32".parse().unwrap());
+
+        let response = store_vote(&pool, poll_id, option_a, &voter()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = store_vote(&pool, poll_id, option_a, &other_voter).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(count_votes(&pool, poll_id).await, 2);
+    }
+
+    #[sqlx::test]
+    async fn voter_tokens_can_only_cast_ballots(pool: sqlx::PgPool) {
+        let (poll_id, option_a, _) = create_poll(&pool, models::PollType::Multiple).await;
+        let mut token_voter = voter();
+        token_voter.id = "token:1".to_string();
+
+        let response = store_vote(&pool, poll_id, option_a, &token_voter).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(count_votes(&pool, poll_id).await, 0);
+    }
+
+    /// sends the votes at once, so they race each other in the database,
+    /// returns how many were accepted
+    async fn hammer(pool: &sqlx::PgPool, poll_id: i64, option_ids: &[i64]) -> usize {
+        let voter = voter();
+        let votes = (0..CONCURRENT_VOTES)
+            .map(|i| store_vote(pool, poll_id, option_ids[i % option_ids.len()], &voter));
+        join_all(votes)
+            .await
+            .iter()
+            .filter(|x| x.status() == StatusCode::OK)
+            .count()
+    }
+
+    #[sqlx::test]
+    async fn concurrent_votes_in_single_poll_store_one_vote(pool: sqlx::PgPool) {
+        let (poll_id, option_a, option_b) = create_poll(&pool, models::PollType::Single).await;
+
+        assert_eq!(hammer(&pool, poll_id, &[option_a, option_b]).await, 1);
+        assert_eq!(count_votes(&pool, poll_id).await, 1);
+    }
+
+    #[sqlx::test]
+    async fn concurrent_votes_for_one_option_in_multiple_poll_store_one_vote(pool: sqlx::PgPool) {
+        let (poll_id, option_a, _) = create_poll(&pool, models::PollType::Multiple).await;
+
+        assert_eq!(hammer(&pool, poll_id, &[option_a]).await, 1);
+        assert_eq!(count_votes(&pool, poll_id).await, 1);
+    }
+}
@@ -1,26 +1,235 @@
+use std::{collections::HashMap, ops::Range};
+
 use actix_web::{
     web::{self, ServiceConfig},
-    HttpResponse, Responder,
+    HttpRequest, HttpResponse, Responder,
 };
+use futures_util::StreamExt;
 use sqlx::QueryBuilder;
+use tokio::sync::broadcast;
 
+use super::{
+    authorize_poll_access, retrieve_poll_id,
+    user::{authenticate_user, require_user},
+    voter::Voter,
+};
 use crate::{
+    auth,
+    background_tasks::ALL_POLLS,
     models::{self, Message},
-    AppData,
+    tally, AppData,
 };
 
 // normally I would split this large file into multiple smaller files,
 // however to make it easier to navigate through the code in this file on GitHub
 // I put it all in one file
 
-async fn get_polls(app_data: web::Data<AppData>) -> impl Responder {
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PollResponseData {
+    #[serde(flatten)]
+    poll: models::Poll,
+    // computed from the timestamps, so clients don't have to compare
+    // them against their own (possibly wrong) clock
+    status: models::PollStatus,
+}
+
+impl From<models::Poll> for PollResponseData {
+    fn from(poll: models::Poll) -> Self {
+        let status = poll.status();
+        Self { poll, status }
+    }
+}
+
+#[derive(serde::Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+enum PollsSort {
+    #[default]
+    Newest,
+    Oldest,
+    ClosingSoon,
+    OpeningSoon,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PollsQuery {
+    limit: Option<i64>,
+    // returned by the previous page, see CURSOR_HEADER
+    cursor: Option<String>,
+    // without it, all polls except scheduled ones are listed
+    status: Option<models::PollStatus>,
+    poll_type: Option<models::PollType>,
+    // case insensitive part of the title
+    title: Option<String>,
+    sort: Option<PollsSort>,
+}
+
+// the results of score polls count every score in the range,
+// has to match the check_score_range_size constraint
+const MAX_SCORES: i64 = 100;
+
+const DEFAULT_POLLS_LIMIT: i64 = 20;
+const MAX_POLLS_LIMIT: i64 = 100;
+const CURSOR_HEADER: &str = "X-Next-Cursor";
+
+// a cursor points to the last poll of a page by the value of the sorted column
+// and its slug, which breaks ties between polls with the same value
+fn encode_cursor(sort: PollsSort, poll: &models::Poll) -> String {
+    let value = match sort {
+        PollsSort::Newest | PollsSort::Oldest => poll.created_at,
+        PollsSort::ClosingSoon => poll.timeout_at,
+        PollsSort::OpeningSoon => poll.opens_at,
+    };
+    format!("{}_{}", value.timestamp_micros(), poll.slug)
+}
+
+fn decode_cursor(cursor: &str) -> Option<(chrono::DateTime<chrono::Utc>, String)> {
+    // slugs can contain _ as well, but timestamps can't
+    let (value, slug) = cursor.split_once('_')?;
+    let value = chrono::NaiveDateTime::from_timestamp_micros(value.parse().ok()?)?;
+    Some((
+        chrono::DateTime::from_utc(value, chrono::Utc),
+        slug.to_string(),
+    ))
+}
+
+// keyset pagination instead of an offset, so pages don't shift
+// when new polls are created while paging through them
+async fn get_polls(
+    app_data: web::Data<AppData>,
+    query: web::Query<PollsQuery>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let query = query.into_inner();
+    let sort = query.sort.unwrap_or_default();
+    let limit = query.limit.unwrap_or(DEFAULT_POLLS_LIMIT);
+    if !(1..=MAX_POLLS_LIMIT).contains(&limit) {
+        return HttpResponse::BadRequest().json(Message("limit has to be between 1 and 100"));
+    }
+    let cursor = match query.cursor.as_deref().map(decode_cursor) {
+        Some(Some(cursor)) => Some(cursor),
+        Some(None) => return HttpResponse::BadRequest().json(Message("invalid cursor")),
+        None => None,
+    };
+
+    let mut query_builder = QueryBuilder::new(
+        "select id, title, poll_type, created_at, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, slug, unlisted, results_visibility, voter_identity from poll where not unlisted and access_code_hash is null and ",
+    );
+    // these have to match the order of the checks in Poll::status
+    query_builder.push(match query.status {
+        None => "opens_at <= now()",
+        Some(models::PollStatus::Scheduled) => "timeout_at > now() and opens_at > now()",
+        Some(models::PollStatus::Open) => "timeout_at > now() and opens_at <= now()",
+        Some(models::PollStatus::Closed) => "delete_at > now() and timeout_at <= now()",
+        Some(models::PollStatus::PendingDeletion) => "delete_at <= now()",
+    });
+    if let Some(poll_type) = &query.poll_type {
+        query_builder.push(" and poll_type = ");
+        query_builder.push_bind(poll_type);
+    }
+    if let Some(title) = &query.title {
+        // strpos instead of like, so % and _ in the search don't need escaping
+        query_builder.push(" and strpos(lower(title), lower(");
+        query_builder.push_bind(title);
+        query_builder.push(")) > 0");
+    }
+    let (column, comparison, direction) = match sort {
+        PollsSort::Newest => ("created_at", "<", "desc"),
+        PollsSort::Oldest => ("created_at", ">", "asc"),
+        PollsSort::ClosingSoon => ("timeout_at", ">", "asc"),
+        PollsSort::OpeningSoon => ("opens_at", ">", "asc"),
+    };
+    if let Some((value, slug)) = cursor {
+        query_builder.push(format!(" and ({}, slug) {} (", column, comparison));
+        query_builder.push_bind(value);
+        query_builder.push(", ");
+        query_builder.push_bind(slug);
+        query_builder.push(")");
+    }
+    query_builder.push(format!(
+        " order by {} {}, slug {} limit ",
+        column, direction, direction
+    ));
+    // one more than requested, to know whether there is a next page
+    query_builder.push_bind(limit + 1);
+
+    let polls_result = query_builder
+        .build_query_as::<models::Poll>()
+        .fetch_all(pool)
+        .await;
+    let mut polls =
+        unwrap_or_log_and_internal_server_error_response!(polls_result, "internal server error");
+
+    let mut response = HttpResponse::Ok();
+    if polls.len() as i64 > limit {
+        polls.truncate(limit as usize);
+        if let Some(last_poll) = polls.last() {
+            let next_cursor = encode_cursor(sort, last_poll);
+            // the same query, except for the cursor
+            let mut query_string: Vec<&str> = request
+                .query_string()
+                .split('&')
+                .filter(|x| !x.is_empty() && !x.starts_with("cursor="))
+                .collect();
+            let cursor_parameter = format!("cursor={}", next_cursor);
+            query_string.push(&cursor_parameter);
+            response.insert_header((
+                "Link",
+                format!(
+                    "<{}?{}>; rel=\"next\"",
+                    request.path(),
+                    query_string.join("&")
+                ),
+            ));
+            response.insert_header((CURSOR_HEADER, next_cursor));
+        }
+    }
+    response.json(
+        polls
+            .into_iter()
+            .map(PollResponseData::from)
+            .collect::<Vec<_>>(),
+    )
+}
+
+// scheduled polls are listed separately, ordered by when they open,
+// the same as /polls?status=scheduled&sort=openingSoon, with the same pages
+async fn get_upcoming_polls(
+    app_data: web::Data<AppData>,
+    query: web::Query<PollsQuery>,
+    request: HttpRequest,
+) -> impl Responder {
+    let mut query = query.into_inner();
+    query.status = Some(models::PollStatus::Scheduled);
+    query.sort = Some(query.sort.unwrap_or(PollsSort::OpeningSoon));
+    get_polls(app_data, web::Query(query), request).await
+}
+
+// including unlisted and private polls, the owner can see them anyway
+pub(super) async fn get_owned_polls(
+    app_data: web::Data<AppData>,
+    request: HttpRequest,
+) -> impl Responder {
     let pool = &app_data.pool;
+    let user_id = match require_user(pool, &request).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
     let polls = sqlx::query_as!(
         models::Poll,
-        r#"select id, title, poll_type as "poll_type!: models::PollType", created_at, timeout_at, delete_at from poll"#
+        r#"select id, title, poll_type as "poll_type!: models::PollType", created_at, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, slug, unlisted, results_visibility as "results_visibility!: models::ResultsVisibility", voter_identity as "voter_identity!: models::VoterIdentity" from poll
+        where owner_id = $1 order by created_at desc, slug desc"#,
+        user_id
     ).fetch_all(pool).await;
     match polls {
-        Ok(polls) => HttpResponse::Ok().json(polls),
+        Ok(polls) => HttpResponse::Ok().json(
+            polls
+                .into_iter()
+                .map(PollResponseData::from)
+                .collect::<Vec<_>>(),
+        ),
         Err(e) => {
             log::error!("{}", e);
             HttpResponse::InternalServerError().json(Message("internal server error"))
@@ -28,16 +237,129 @@ async fn get_polls(app_data: web::Data<AppData>) -> impl Responder {
     }
 }
 
-async fn get_poll(app_data: web::Data<AppData>, path_id: web::Path<i64>) -> impl Responder {
-    let id = path_id.into_inner();
+#[derive(serde::Deserialize)]
+struct PollSearchQuery {
+    // websearch syntax, e.g. "lunch -pizza" or "\"ice cream\" or cake"
+    q: String,
+    limit: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PollSearchResponseData {
+    #[serde(flatten)]
+    poll: PollResponseData,
+    rank: f32,
+    // the title and the matching option names as escaped html, with matches in <b></b>
+    title_headline: String,
+    option_headlines: Vec<String>,
+}
+
+// matches in the title and in the options add up,
+// so polls that match in both are ranked higher
+async fn search_polls(
+    app_data: web::Data<AppData>,
+    query: web::Query<PollSearchQuery>,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let query = query.into_inner();
+    if query.q.trim().is_empty() {
+        return HttpResponse::BadRequest().json(Message("q must not be empty"));
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_POLLS_LIMIT);
+    if !(1..=MAX_POLLS_LIMIT).contains(&limit) {
+        return HttpResponse::BadRequest().json(Message("limit has to be between 1 and 100"));
+    }
+
+    // polls and options are matched separately, so both gin indexes can be used,
+    // headlines are only computed for the polls that are returned
+    let search_result = sqlx::query!(
+        r#"with search as (select websearch_to_tsquery('simple', $1) as query),
+        matching_polls as (
+            select poll.id from poll, search where poll.search_vector @@ search.query
+            union
+            select poll_option.poll_id from poll_option, search
+            where poll_option.search_vector @@ search.query
+        ),
+        ranked_polls as (
+            select poll.id, ts_rank(poll.search_vector, search.query) + coalesce((
+                select max(ts_rank(poll_option.search_vector, search.query)) from poll_option
+                where poll_option.poll_id = poll.id and poll_option.search_vector @@ search.query
+            ), 0) as rank
+            from matching_polls
+            join poll on poll.id = matching_polls.id
+            cross join search
+            where not unlisted and access_code_hash is null and opens_at <= now()
+            order by rank desc, poll.created_at desc
+            limit $2
+        )
+        select poll.id, title, poll_type as "poll_type!: models::PollType", created_at, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, slug, unlisted, results_visibility as "results_visibility!: models::ResultsVisibility", voter_identity as "voter_identity!: models::VoterIdentity",
+        ranked_polls.rank as "rank!",
+        ts_headline('simple', escape_html(title), search.query, 'HighlightAll=true') as "title_headline!",
+        array(
+            select ts_headline('simple', escape_html(poll_option.name), search.query, 'HighlightAll=true')
+            from poll_option
+            where poll_option.poll_id = poll.id and poll_option.search_vector @@ search.query
+            order by poll_option.id
+        ) as "option_headlines!"
+        from ranked_polls
+        join poll on poll.id = ranked_polls.id
+        cross join search
+        order by ranked_polls.rank desc, poll.created_at desc"#,
+        query.q,
+        limit
+    )
+    .fetch_all(pool)
+    .await;
+    let matches =
+        unwrap_or_log_and_internal_server_error_response!(search_result, "internal server error");
+
+    HttpResponse::Ok().json(
+        matches
+            .into_iter()
+            .map(|x| PollSearchResponseData {
+                poll: PollResponseData::from(models::Poll {
+                    id: x.id,
+                    title: x.title,
+                    poll_type: x.poll_type,
+                    created_at: x.created_at,
+                    opens_at: x.opens_at,
+                    timeout_at: x.timeout_at,
+                    delete_at: x.delete_at,
+                    min_score: x.min_score,
+                    max_score: x.max_score,
+                    min_choices: x.min_choices,
+                    max_choices: x.max_choices,
+                    slug: x.slug,
+                    unlisted: x.unlisted,
+                    results_visibility: x.results_visibility,
+                    voter_identity: x.voter_identity,
+                }),
+                rank: x.rank,
+                title_headline: x.title_headline,
+                option_headlines: x.option_headlines,
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+async fn get_poll(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
     let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
     let poll = sqlx::query_as!(
         models::Poll,
-        r#"select id, title, poll_type as "poll_type!: models::PollType", created_at, timeout_at, delete_at from poll where id = $1"#,
+        r#"select id, title, poll_type as "poll_type!: models::PollType", created_at, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, slug, unlisted, results_visibility as "results_visibility!: models::ResultsVisibility", voter_identity as "voter_identity!: models::VoterIdentity" from poll where id = $1"#,
         &id as &i64
     ).fetch_one(pool).await;
     match poll {
-        Ok(poll) => HttpResponse::Ok().json(poll),
+        Ok(poll) => HttpResponse::Ok().json(PollResponseData::from(poll)),
         Err(sqlx::Error::RowNotFound) => HttpResponse::NotFound().json(Message("no such poll")),
         Err(e) => {
             log::error!("{}", e);
@@ -48,7 +370,6 @@ async fn get_poll(app_data: web::Data<AppData>, path_id: web::Path<i64>) -> impl
 
 #[derive(Debug, serde::Serialize)]
 struct PollCount {
-    poll_id: i64,
     option_id: i64,
     option_name: String,
     count: i64,
@@ -61,11 +382,21 @@ const fn get_color(index: usize) -> &'static RGBColor {
 }
 
 use anyhow::Result;
-use plotters::prelude::*;
+use plotters::{
+    coord::ranged1d::{AsRangedCoord, ValueFormatter},
+    prelude::*,
+};
 
 // technically this could also take some kind of Theme Enum
 // to allow for dark mode or something like this
-fn draw_bar_graph(caption: &str, data: &[PollCount]) -> Result<String> {
+// the value of each bar is generic, so counts can be drawn as well as average scores
+// all bars start at the beginning of the range
+fn draw_bar_graph<T>(caption: &str, data: &[(String, T)], range: Range<T>) -> Result<String>
+where
+    T: Copy + 'static,
+    Range<T>: AsRangedCoord<Value = T>,
+    <Range<T> as AsRangedCoord>::CoordDescType: ValueFormatter<T>,
+{
     let mut buffer = String::new();
 
     let data_len = data.len();
@@ -75,7 +406,7 @@ fn draw_bar_graph(caption: &str, data: &[PollCount]) -> Result<String> {
     let root_area = svg_backend.into_drawing_area();
     root_area.fill(&WHITE)?;
 
-    let max_count = data.iter().map(|x| x.count).max().unwrap_or(0);
+    let bar_start = range.start;
 
     let mut context = ChartBuilder::on(&root_area)
         // might need to be changed according to size of biggest label
@@ -85,25 +416,25 @@ fn draw_bar_graph(caption: &str, data: &[PollCount]) -> Result<String> {
         .set_label_area_size(LabelAreaPosition::Bottom, 40)
         .caption(caption, ("sans-serif", 40))
         // -1 because the upper bound is included, even though the range is exclusive
-        .build_cartesian_2d(0..max_count, (0..data.len() - 1).into_segmented())?;
+        .build_cartesian_2d(range, (0..data.len() - 1).into_segmented())?;
 
     context
         .configure_mesh()
         .y_label_formatter(&|x| match x {
-            SegmentValue::CenterOf(x) => data[data_len - *x - 1].option_name.clone(),
+            SegmentValue::CenterOf(x) => data[data_len - *x - 1].0.clone(),
             // this does not wrap the text
             // SegmentValue::CenterOf(x) => "hey\ntest\ncool".to_string(),
             _ => "".to_string(),
         })
         .draw()?;
 
-    let data_values = data.iter().map(|x| x.count);
+    let data_values = data.iter().map(|x| x.1);
 
     context.draw_series((0..).zip(data_values).map(|(y, x)| {
         let reversed_y = data_len - y - 1;
         let mut bar = Rectangle::new(
             [
-                (0, SegmentValue::Exact(reversed_y)),
+                (bar_start, SegmentValue::Exact(reversed_y)),
                 (x, SegmentValue::Exact(reversed_y + 1)),
             ],
             get_color(y).filled(),
@@ -136,9 +467,68 @@ async fn get_poll_name(poll_id: i64, pool: &sqlx::PgPool) -> Result<String> {
     Ok(poll_name.title)
 }
 
-async fn get_poll_graph(app_data: web::Data<AppData>, path_id: web::Path<i64>) -> impl Responder {
-    let id = path_id.into_inner();
+fn draw_score_graph(
+    caption: &str,
+    poll_options: &[models::PollOption],
+    score_range: (i32, i32),
+    votes: &[(i64, i32)],
+) -> Result<String> {
+    let option_ids: Vec<i64> = poll_options.iter().map(|x| x.id).collect();
+    let result = tally::score(&option_ids, score_range, votes);
+    let data: Vec<(String, f64)> = poll_options
+        .iter()
+        .zip(result.options)
+        // options without votes get an empty bar
+        .map(|(option, scores)| (option.name.clone(), scores.average.unwrap_or(0.0)))
+        .collect();
+    let (min_score, max_score) = score_range;
+    draw_bar_graph(caption, &data, min_score as f64..max_score as f64)
+}
+
+async fn get_poll_graph(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
     let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_results_access(pool, id, &request).await {
+        return response;
+    }
+
+    let poll_result = sqlx::query!(
+        r#"select title, min_score, max_score from poll where id = $1"#,
+        &id as &i64
+    )
+    .fetch_optional(pool)
+    .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+    let poll = match poll {
+        Some(poll) => poll,
+        None => return HttpResponse::NotFound().json(Message("no such poll")),
+    };
+    // score polls show the average score of every option instead of the count
+    if let (Some(min_score), Some(max_score)) = (poll.min_score, poll.max_score) {
+        let poll_options = unwrap_or_log_and_internal_server_error_response!(
+            retrieve_poll_options(pool, id).await,
+            "internal server error"
+        );
+        let votes = unwrap_or_log_and_internal_server_error_response!(
+            retrieve_scores(pool, id).await,
+            "internal server error"
+        );
+        let svg_content = unwrap_or_log_and_internal_server_error_response!(
+            draw_score_graph(&poll.title, &poll_options, (min_score, max_score), &votes),
+            "internal server error"
+        );
+        return HttpResponse::Ok()
+            .content_type("image/svg+xml")
+            .body(svg_content);
+    }
 
     let poll_count_result = retrieve_poll_counts(pool, id).await;
 
@@ -151,7 +541,12 @@ async fn get_poll_graph(app_data: web::Data<AppData>, path_id: web::Path<i64>) -
                 get_poll_name(id, pool).await,
                 "internal server error"
             );
-            let svg_content_result = draw_bar_graph(&poll_title, &poll_count);
+            let max_count = poll_count.iter().map(|x| x.count).max().unwrap_or(0);
+            let data: Vec<(String, i64)> = poll_count
+                .into_iter()
+                .map(|x| (x.option_name, x.count))
+                .collect();
+            let svg_content_result = draw_bar_graph(&poll_title, &data, 0..max_count);
             let svg_content = unwrap_or_log_and_internal_server_error_response!(
                 svg_content_result,
                 "internal server error"
@@ -177,9 +572,12 @@ async fn retrieve_poll_counts(pool: &sqlx::PgPool, poll_id: i64) -> sqlx::Result
     // TODO: check if poll with given id exists and if not return None
     sqlx::query_as!(
         PollCount,
-        r#"select poll.id as poll_id, poll_option.id as option_id, poll_option.name as option_name,
+        r#"select poll_option.id as option_id, poll_option.name as option_name,
         (select count(poll_vote.id)
-            from poll_vote where poll_vote.option_id = poll_option.id) as "count!: i64"
+            from poll_vote where poll_vote.option_id = poll_option.id
+            and poll_vote.retracted_at is null
+            -- for ranked polls only the first preferences are counted
+            and (poll_vote.rank is null or poll_vote.rank = 1)) as "count!: i64"
         from poll, poll_option where poll_option.poll_id = poll.id and poll.id = $1"#,
         poll_id
     )
@@ -187,9 +585,19 @@ async fn retrieve_poll_counts(pool: &sqlx::PgPool, poll_id: i64) -> sqlx::Result
     .await
 }
 
-async fn get_poll_votes(app_data: web::Data<AppData>, path_id: web::Path<i64>) -> impl Responder {
-    let id = path_id.into_inner();
+async fn get_poll_votes(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
     let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_results_access(pool, id, &request).await {
+        return response;
+    }
     let poll_counts_result = retrieve_poll_counts(pool, id).await;
     match poll_counts_result {
         Ok(poll_counts) => {
@@ -211,13 +619,468 @@ async fn get_poll_votes(app_data: web::Data<AppData>, path_id: web::Path<i64>) -
     }
 }
 
+const HEARTBEAT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+struct VotesStreamState {
+    pool: sqlx::PgPool,
+    poll_id: i64,
+    poll_updates: broadcast::Receiver<i64>,
+    heartbeat: tokio::time::Interval,
+    // None once the closed event has been sent
+    timeout_at: Option<chrono::DateTime<chrono::Utc>>,
+    counts_changed: bool,
+}
+
+fn format_event(event: &str, data: &str) -> String {
+    format!("event: {}\ndata: {}\n\n", event, data)
+}
+
+/// the next server-sent event, None ends the stream
+async fn next_votes_event(state: &mut VotesStreamState) -> Option<String> {
+    loop {
+        let timeout_at = state.timeout_at?;
+        let is_closed = timeout_at <= chrono::Utc::now();
+        if state.counts_changed || is_closed {
+            state.counts_changed = false;
+            let poll_counts = match retrieve_poll_counts(&state.pool, state.poll_id).await {
+                Ok(poll_counts) if !poll_counts.is_empty() => poll_counts,
+                // the poll has been deleted
+                Ok(_) => return None,
+                Err(e) => {
+                    log::error!("{}", e);
+                    return None;
+                }
+            };
+            let data = serde_json::to_string(&poll_counts).ok()?;
+            // the final counts are sent with the closed event
+            if is_closed {
+                state.timeout_at = None;
+                return Some(format_event("closed", &data));
+            }
+            return Some(format_event("votes", &data));
+        }
+
+        let until_timeout = (timeout_at - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        tokio::select! {
+            poll_update = state.poll_updates.recv() => match poll_update {
+                Ok(poll_id) if poll_id != state.poll_id && poll_id != ALL_POLLS => continue,
+                // ALL_POLLS or Lagged: updates were missed,
+                // so it's unknown whether one was for this poll
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return None,
+            },
+            _ = state.heartbeat.tick() => return Some(format_event("heartbeat", "")),
+            _ = tokio::time::sleep(until_timeout) => continue,
+        }
+        // the poll might have been closed early or edited
+        let poll_result = sqlx::query!(
+            r#"select timeout_at from poll where id = $1"#,
+            state.poll_id
+        )
+        .fetch_optional(&state.pool)
+        .await;
+        match poll_result {
+            Ok(Some(poll)) => state.timeout_at = Some(poll.timeout_at),
+            Ok(None) => return None,
+            Err(e) => {
+                log::error!("{}", e);
+                return None;
+            }
+        }
+        state.counts_changed = true;
+    }
+}
+
+// sends the current counts right away and then again after every change,
+// until the poll closes, which sends the final counts as closed event
+async fn get_poll_votes_stream(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_results_access(pool, id, &request).await {
+        return response;
+    }
+    // subscribe before reading the poll, so no update can be missed in between
+    let poll_updates = app_data.poll_updates.subscribe();
+    let poll_result = sqlx::query!(r#"select timeout_at from poll where id = $1"#, id)
+        .fetch_one(pool)
+        .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+
+    let state = VotesStreamState {
+        pool: pool.clone(),
+        poll_id: id,
+        poll_updates,
+        heartbeat: tokio::time::interval_at(
+            tokio::time::Instant::now() + HEARTBEAT_INTERVAL,
+            HEARTBEAT_INTERVAL,
+        ),
+        timeout_at: Some(poll.timeout_at),
+        counts_changed: true,
+    };
+    let stream = futures_util::stream::unfold(state, |mut state| async move {
+        let event = next_votes_event(&mut state).await?;
+        Some((Ok::<_, actix_web::Error>(web::Bytes::from(event)), state))
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum SocketClientMessageType {
+    // start and stop receiving the counts after every change
+    Subscribe,
+    Unsubscribe,
+    Vote,
+    Ballot,
+}
+
+#[derive(serde::Deserialize)]
+struct SocketClientFrame {
+    #[serde(rename = "type")]
+    message_type: SocketClientMessageType,
+    // the other fields depend on the type
+    #[serde(flatten)]
+    data: serde_json::Value,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SocketVoteData {
+    option_id: i64,
+}
+
+enum SocketClientMessage {
+    Subscribe,
+    Unsubscribe,
+    Vote(SocketVoteData),
+    Ballot(BallotPostRequestData),
+}
+
+impl SocketClientMessage {
+    // this isn't an internally tagged enum, since those
+    // can't deserialize the integer keys of the scores in a ballot
+    fn parse(text: &str) -> serde_json::Result<Self> {
+        let frame: SocketClientFrame = serde_json::from_str(text)?;
+        Ok(match frame.message_type {
+            SocketClientMessageType::Subscribe => Self::Subscribe,
+            SocketClientMessageType::Unsubscribe => Self::Unsubscribe,
+            SocketClientMessageType::Vote => Self::Vote(serde_json::from_value(frame.data)?),
+            SocketClientMessageType::Ballot => Self::Ballot(serde_json::from_value(frame.data)?),
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum SocketServerMessage {
+    Votes {
+        counts: Vec<PollCount>,
+    },
+    // the final counts, only for subscribers who may see the results,
+    // the connection is closed afterwards
+    Closed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        counts: Option<Vec<PollCount>>,
+    },
+    // the connection is closed afterwards
+    Deleted,
+    // the answer to a vote, ballot or subscription, with the same status
+    // and body the corresponding http endpoint would have responded with
+    Response {
+        status: u16,
+        body: serde_json::Value,
+    },
+}
+
+impl SocketServerMessage {
+    async fn from_response(response: HttpResponse) -> Self {
+        let status = response.status().as_u16();
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .ok()
+            .and_then(|x| serde_json::from_slice(&x).ok())
+            .unwrap_or(serde_json::Value::Null);
+        SocketServerMessage::Response { status, body }
+    }
+}
+
+struct PollSocket {
+    app_data: web::Data<AppData>,
+    poll_id: i64,
+    // the request that opened the connection, every vote is cast as
+    // the voter who opened it and the access to results is checked with it
+    request: HttpRequest,
+    // None if it could not be resolved when connecting,
+    // e.g. spectators of invite-only polls don't have a voter token
+    voter: Option<Voter>,
+    session: actix_ws::Session,
+    subscribed: bool,
+}
+
+impl PollSocket {
+    /// false if the connection has been closed
+    async fn send(&mut self, message: &SocketServerMessage) -> bool {
+        match serde_json::to_string(message) {
+            Ok(text) => self.session.text(text).await.is_ok(),
+            Err(e) => {
+                log::error!("{}", e);
+                false
+            }
+        }
+    }
+
+    /// the voter resolved when connecting, otherwise resolves it again,
+    /// so voting responds with the reason it failed
+    async fn resolve_voter(&mut self) -> Result<&Voter, HttpResponse> {
+        let voter = match self.voter.take() {
+            Some(voter) => voter,
+            None => Voter::resolve(&self.app_data.pool, self.poll_id, &self.request).await?,
+        };
+        Ok(self.voter.insert(voter))
+    }
+
+    /// false if the connection has been closed
+    async fn handle_text(&mut self, text: &str) -> bool {
+        let message = match SocketClientMessage::parse(text) {
+            Ok(message) => message,
+            Err(e) => {
+                return self
+                    .send(&SocketServerMessage::Response {
+                        status: 400,
+                        body: serde_json::json!({ "message": e.to_string() }),
+                    })
+                    .await
+            }
+        };
+        let app_data = self.app_data.clone();
+        let pool = &app_data.pool;
+        let response = match message {
+            SocketClientMessage::Subscribe => {
+                if let Err(response) =
+                    authorize_results_access(pool, self.poll_id, &self.request).await
+                {
+                    response
+                } else {
+                    self.subscribed = true;
+                    return self.send_counts().await;
+                }
+            }
+            SocketClientMessage::Unsubscribe => {
+                self.subscribed = false;
+                HttpResponse::Ok().json(Message("unsubscribed"))
+            }
+            SocketClientMessage::Vote(vote) => {
+                let poll_id = self.poll_id;
+                match self.resolve_voter().await {
+                    Ok(voter) => {
+                        super::option::store_vote(pool, poll_id, vote.option_id, voter).await
+                    }
+                    Err(response) => response,
+                }
+            }
+            SocketClientMessage::Ballot(ballot) => {
+                let poll_id = self.poll_id;
+                match self.resolve_voter().await {
+                    Ok(voter) => store_ballot(pool, poll_id, voter, ballot, false).await,
+                    Err(response) => response,
+                }
+            }
+        };
+        let message = SocketServerMessage::from_response(response).await;
+        self.send(&message).await
+    }
+
+    /// false if the connection has been closed
+    async fn send_counts(&mut self) -> bool {
+        // results_visibility might only allow them after voting or closing
+        if authorize_results_access(&self.app_data.pool, self.poll_id, &self.request)
+            .await
+            .is_err()
+        {
+            return true;
+        }
+        match retrieve_poll_counts(&self.app_data.pool, self.poll_id).await {
+            Ok(counts) => self.send(&SocketServerMessage::Votes { counts }).await,
+            Err(e) => {
+                log::error!("{}", e);
+                false
+            }
+        }
+    }
+
+    async fn send_closed(&mut self) {
+        // results_visibility might still not allow them, e.g. for non-voters
+        let is_allowed = self.subscribed
+            && authorize_results_access(&self.app_data.pool, self.poll_id, &self.request)
+                .await
+                .is_ok();
+        let counts = if is_allowed {
+            match retrieve_poll_counts(&self.app_data.pool, self.poll_id).await {
+                Ok(counts) => Some(counts),
+                Err(e) => {
+                    log::error!("{}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        self.send(&SocketServerMessage::Closed { counts }).await;
+    }
+
+    /// None if the poll has been deleted
+    async fn retrieve_timeout_at(&self) -> sqlx::Result<Option<chrono::DateTime<chrono::Utc>>> {
+        let poll = sqlx::query!(r#"select timeout_at from poll where id = $1"#, self.poll_id)
+            .fetch_optional(&self.app_data.pool)
+            .await?;
+        Ok(poll.map(|x| x.timeout_at))
+    }
+
+    async fn run(mut self, mut messages: actix_ws::MessageStream) {
+        let mut poll_updates = self.app_data.poll_updates.subscribe();
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut timeout_at = match self.retrieve_timeout_at().await {
+            Ok(Some(timeout_at)) => timeout_at,
+            Ok(None) => {
+                self.send(&SocketServerMessage::Deleted).await;
+                let _ = self.session.close(None).await;
+                return;
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                let _ = self.session.close(None).await;
+                return;
+            }
+        };
+
+        loop {
+            let until_timeout = (timeout_at - chrono::Utc::now())
+                .to_std()
+                .unwrap_or_default();
+            let is_open = tokio::select! {
+                message = messages.next() => match message {
+                    Some(Ok(actix_ws::Message::Text(text))) => self.handle_text(&text).await,
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => self.session.pong(&bytes).await.is_ok(),
+                    Some(Ok(actix_ws::Message::Close(_))) | Some(Err(_)) | None => false,
+                    Some(Ok(_)) => true,
+                },
+                poll_update = poll_updates.recv() => match poll_update {
+                    Ok(poll_id) if poll_id != self.poll_id && poll_id != ALL_POLLS => continue,
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                        match self.retrieve_timeout_at().await {
+                            Ok(Some(new_timeout_at)) => {
+                                timeout_at = new_timeout_at;
+                                !self.subscribed || self.send_counts().await
+                            }
+                            Ok(None) => {
+                                self.send(&SocketServerMessage::Deleted).await;
+                                false
+                            }
+                            Err(e) => {
+                                log::error!("{}", e);
+                                false
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => false,
+                },
+                _ = heartbeat.tick() => self.session.ping(b"").await.is_ok(),
+                _ = tokio::time::sleep(until_timeout) => {
+                    self.send_closed().await;
+                    false
+                },
+            };
+            if !is_open {
+                break;
+            }
+        }
+        let _ = self.session.close(None).await;
+    }
+}
+
+// subscribing to counts and voting over a single connection,
+// the messages are json objects with a type field, see SocketClientMessage
+async fn get_poll_socket(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+    body: web::Payload,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    // browsers can't send headers with websockets,
+    // so the access code has to be in the query
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let voter = Voter::resolve(pool, id, &request).await.ok();
+    let (response, session, messages) = match actix_ws::handle(&request, body) {
+        Ok(handshake) => handshake,
+        Err(e) => return e.error_response(),
+    };
+    // a new voter cookie can only be set with the handshake
+    let response = match &voter {
+        Some(voter) => voter.respond(response),
+        None => response,
+    };
+    let socket = PollSocket {
+        app_data: app_data.clone(),
+        poll_id: id,
+        request,
+        voter,
+        session,
+        subscribed: false,
+    };
+    actix_web::rt::spawn(socket.run(messages));
+    response
+}
+
 #[derive(serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct PollPostRequestData {
     title: String,
     poll_type: models::PollType,
+    // defaults to the creation of the poll
+    opens_at: Option<chrono::DateTime<chrono::Utc>>,
     timeout_at: Option<chrono::DateTime<chrono::Utc>>,
     delete_at: Option<chrono::DateTime<chrono::Utc>>,
+    // only allowed for score polls, defaults to 0 to 5
+    min_score: Option<i32>,
+    max_score: Option<i32>,
+    // only allowed for multiple polls, if either is set votes have
+    // to be submitted as ballots
+    // defaults to at least one and at most all options
+    min_choices: Option<i32>,
+    max_choices: Option<i32>,
+    // unlisted polls are only accessible by their slug
+    #[serde(default)]
+    unlisted: bool,
+    // makes the poll private, it's not listed and
+    // viewing or voting requires the access code
+    access_code: Option<String>,
+    // defaults to always
+    #[serde(default)]
+    results_visibility: models::ResultsVisibility,
+    // how voters are told apart, defaults to their ip address
+    #[serde(default)]
+    voter_identity: models::VoterIdentity,
+    // all addresses within networks of these prefix lengths count as the same voter,
+    // default to the prefix lengths of the instance
+    ipv4_prefix_length: Option<i32>,
+    ipv6_prefix_length: Option<i32>,
     poll_options: Vec<String>,
 }
 
@@ -227,13 +1090,15 @@ struct PollPostResponseData {
     #[serde(flatten)]
     poll: models::Poll,
     poll_options: Vec<models::PollOption>,
+    // only the hash is stored, so this is the only time it can be retrieved
+    admin_token: String,
 }
 
 // the names indicates that this returns a boolean
 // should it be renamed?
 // no, since it kind of acts like a boolean, however you additionally get
 // the reason why it is not valid
-fn are_poll_options_valid(poll_options: &Vec<String>) -> Result<(), &str> {
+fn are_poll_options_valid(poll_options: &[String]) -> Result<(), &'static str> {
     if poll_options.len() < 2 {
         return Err("At least two poll options are required");
     }
@@ -260,14 +1125,40 @@ fn are_poll_options_valid(poll_options: &Vec<String>) -> Result<(), &str> {
 async fn post_poll(
     app_data: web::Data<AppData>,
     poll: web::Json<PollPostRequestData>,
+    request: HttpRequest,
 ) -> impl Responder {
     let pool = &app_data.pool;
-    let request_data = poll.into_inner();
+    let mut request_data = poll.into_inner();
+
+    // polls created while logged in are owned by the user
+    let owner_id = match authenticate_user(pool, &request).await {
+        Ok(owner_id) => owner_id,
+        Err(response) => return response,
+    };
+    if owner_id.is_none() && !app_data.allow_anonymous_polls {
+        return HttpResponse::Unauthorized().json(Message("creating polls requires logging in"));
+    }
 
     // check title length bigger than 0
     if request_data.title.is_empty() {
         return HttpResponse::BadRequest().json(Message("title is empty"));
     }
+    // the defaults of the database are relative to the creation,
+    // for scheduled polls they should be relative to opens_at instead
+    if let Some(opens_at) = request_data.opens_at {
+        if opens_at < chrono::Utc::now() {
+            return HttpResponse::BadRequest().json(Message("opens_at is in the past"));
+        }
+        let timeout_at = *request_data
+            .timeout_at
+            .get_or_insert(opens_at + chrono::Duration::minutes(30));
+        if timeout_at <= opens_at {
+            return HttpResponse::BadRequest().json(Message("timeout_at is lower than opens_at"));
+        }
+        request_data
+            .delete_at
+            .get_or_insert(timeout_at.max(opens_at + chrono::Duration::days(7)));
+    }
     // check timeout_at is in the future
     if let Some(timeout_at) = request_data.timeout_at {
         if timeout_at < chrono::Utc::now() {
@@ -289,27 +1180,135 @@ async fn post_poll(
         return HttpResponse::BadRequest().json(e);
     }
 
-    let mut query_builder =
-        QueryBuilder::new("insert into poll (title, poll_type, timeout_at, delete_at) values (");
+    let score_range = if request_data.poll_type == models::PollType::Score {
+        let min_score = request_data.min_score.unwrap_or(0);
+        let max_score = request_data.max_score.unwrap_or(5);
+        if min_score >= max_score {
+            return HttpResponse::BadRequest()
+                .json(Message("min_score is not lower than max_score"));
+        }
+        if max_score as i64 - min_score as i64 >= MAX_SCORES {
+            return HttpResponse::BadRequest()
+                .json(Message("the score range can't have more than 100 scores"));
+        }
+        Some((min_score, max_score))
+    } else {
+        if request_data.min_score.is_some() || request_data.max_score.is_some() {
+            return HttpResponse::BadRequest()
+                .json(Message("only score polls can have a score range"));
+        }
+        None
+    };
+
+    let choice_limits = if request_data.min_choices.is_none() && request_data.max_choices.is_none()
+    {
+        None
+    } else if request_data.poll_type == models::PollType::Multiple {
+        let min_choices = request_data.min_choices.unwrap_or(1);
+        let max_choices = request_data
+            .max_choices
+            .unwrap_or(request_data.poll_options.len() as i32);
+        if min_choices < 1 {
+            return HttpResponse::BadRequest().json(Message("min_choices is lower than one"));
+        }
+        if min_choices > request_data.poll_options.len() as i32 {
+            return HttpResponse::BadRequest().json(Message(
+                "min_choices is higher than the number of poll options",
+            ));
+        }
+        if max_choices < min_choices {
+            return HttpResponse::BadRequest()
+                .json(Message("max_choices is lower than min_choices"));
+        }
+        Some((min_choices, max_choices))
+    } else {
+        return HttpResponse::BadRequest()
+            .json(Message("only multiple polls can have choice limits"));
+    };
+
+    if request_data
+        .ipv4_prefix_length
+        .is_some_and(|x| !(0..=32).contains(&x))
+    {
+        return HttpResponse::BadRequest()
+            .json(Message("ipv4_prefix_length has to be between 0 and 32"));
+    }
+    if request_data
+        .ipv6_prefix_length
+        .is_some_and(|x| !(0..=128).contains(&x))
+    {
+        return HttpResponse::BadRequest()
+            .json(Message("ipv6_prefix_length has to be between 0 and 128"));
+    }
+
+    let access_code_hash = match request_data.access_code.clone() {
+        Some(access_code) if access_code.is_empty() => {
+            return HttpResponse::BadRequest().json(Message("access_code is empty"));
+        }
+        Some(access_code) if access_code.chars().count() > super::MAX_ACCESS_CODE_LENGTH => {
+            return HttpResponse::BadRequest().json(Message("access_code is too long"));
+        }
+        Some(access_code) => match super::hash_password(access_code).await {
+            Ok(access_code_hash) => Some(access_code_hash),
+            Err(response) => return response,
+        },
+        None => None,
+    };
+
+    let admin_token = auth::generate_token();
+
+    let mut query_builder = QueryBuilder::new(
+        "insert into poll (title, poll_type, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, admin_token_hash, unlisted, access_code_hash, results_visibility, ipv4_prefix_length, ipv6_prefix_length, voter_identity, owner_id) values (",
+    );
     query_builder.push_bind(&request_data.title);
     query_builder.push(", ");
     query_builder.push_bind(&request_data.poll_type);
     query_builder.push(", ");
+    if let Some(opens_at) = request_data.opens_at {
+        query_builder.push_bind(opens_at);
+    } else {
+        query_builder.push("default");
+    }
+    query_builder.push(", ");
     // we can't remap the option, since for the value
     // we have to call push_bind and in case of absence
     // of a value we have to use the default
     if let Some(timeout_at) = request_data.timeout_at {
-        query_builder.push_bind(timeout_at.to_rfc3339());
+        query_builder.push_bind(timeout_at);
     } else {
         query_builder.push("default");
     }
     query_builder.push(", ");
     if let Some(delete_at) = request_data.delete_at {
-        query_builder.push_bind(delete_at.to_rfc3339());
+        query_builder.push_bind(delete_at);
     } else {
         query_builder.push("default");
     }
-    query_builder.push(r#") returning id, title, poll_type, created_at, timeout_at, delete_at"#);
+    query_builder.push(", ");
+    query_builder.push_bind(score_range.map(|x| x.0));
+    query_builder.push(", ");
+    query_builder.push_bind(score_range.map(|x| x.1));
+    query_builder.push(", ");
+    query_builder.push_bind(choice_limits.map(|x| x.0));
+    query_builder.push(", ");
+    query_builder.push_bind(choice_limits.map(|x| x.1));
+    query_builder.push(", ");
+    query_builder.push_bind(auth::hash_token(&admin_token));
+    query_builder.push(", ");
+    query_builder.push_bind(request_data.unlisted);
+    query_builder.push(", ");
+    query_builder.push_bind(access_code_hash);
+    query_builder.push(", ");
+    query_builder.push_bind(&request_data.results_visibility);
+    query_builder.push(", ");
+    query_builder.push_bind(request_data.ipv4_prefix_length);
+    query_builder.push(", ");
+    query_builder.push_bind(request_data.ipv6_prefix_length);
+    query_builder.push(", ");
+    query_builder.push_bind(&request_data.voter_identity);
+    query_builder.push(", ");
+    query_builder.push_bind(owner_id);
+    query_builder.push(r#") returning id, title, poll_type, created_at, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, slug, unlisted, results_visibility, voter_identity"#);
 
     let query = query_builder.build_query_as::<models::Poll>();
     // we need to start a transaction
@@ -336,14 +1335,11 @@ async fn post_poll(
     let mut option_insert_query_builder =
         QueryBuilder::new(r#"insert into poll_option (poll_id, name) "#);
 
-    option_insert_query_builder.push_values(
-        request_data.poll_options.into_iter(),
-        |mut b, poll_option| {
-            b.push_bind(poll.id).push_bind(poll_option);
-        },
-    );
+    option_insert_query_builder.push_values(request_data.poll_options, |mut b, poll_option| {
+        b.push_bind(poll.id).push_bind(poll_option);
+    });
 
-    option_insert_query_builder.push(r#" returning id, name, poll_id"#);
+    option_insert_query_builder.push(r#" returning id, name"#);
 
     let query = option_insert_query_builder.build_query_as::<models::PollOption>();
     let options_insert_result = query.fetch_all(transaction.as_mut()).await;
@@ -374,16 +1370,1023 @@ async fn post_poll(
     let response_data = PollPostResponseData {
         poll,
         poll_options: inserted_poll_options,
+        admin_token,
     };
     HttpResponse::Ok().json(response_data)
 }
 
-pub fn configure_routes(config: &mut ServiceConfig) {
-    config.route("", web::get().to(get_polls));
-    // this could alternatively be done with a header guard that checks for
-    // the accept header, if json, send json, otherwise send an image
-    config.route("/{id}", web::get().to(get_poll));
-    config.route("/{id}/graph", web::get().to(get_poll_graph));
-    config.route("/{id}/votes", web::get().to(get_poll_votes));
-    config.route("", web::post().to(post_poll));
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BallotPostRequestData {
+    // for ranked polls, ordered from most to least preferred
+    // for single and multiple polls, the chosen options in any order
+    #[serde(default)]
+    option_ids: Vec<i64>,
+    // for score polls, the score of every option by its id
+    #[serde(default)]
+    scores: HashMap<i64, i32>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BallotPostResponseData {
+    #[serde(flatten)]
+    ballot: models::PollBallot,
+    votes: Vec<models::PollVote>,
+}
+
+// not every option has to be chosen, but every chosen option
+// has to belong to the poll and can only be chosen once
+fn are_ballot_option_ids_valid(
+    option_ids: &[i64],
+    poll_option_ids: &[i64],
+) -> Result<(), &'static str> {
+    if option_ids.is_empty() {
+        return Err("ballot is empty");
+    }
+    for (i, option_id) in option_ids.iter().enumerate() {
+        if !poll_option_ids.contains(option_id) {
+            return Err("no such poll option");
+        }
+        if option_ids[i + 1..].contains(option_id) {
+            return Err("poll options are not unique");
+        }
+    }
+    Ok(())
+}
+
+fn is_choice_count_valid(
+    choices: usize,
+    choice_limits: (usize, usize),
+) -> Result<(), &'static str> {
+    let (min_choices, max_choices) = choice_limits;
+    if choices < min_choices {
+        return Err("too few poll options chosen");
+    }
+    if choices > max_choices {
+        return Err("too many poll options chosen");
+    }
+    Ok(())
+}
+
+// unlike a ranking, every option has to be scored
+fn is_score_ballot_valid(
+    scores: &HashMap<i64, i32>,
+    poll_option_ids: &[i64],
+    score_range: (i32, i32),
+) -> Result<(), &'static str> {
+    if scores
+        .keys()
+        .any(|option_id| !poll_option_ids.contains(option_id))
+    {
+        return Err("no such poll option");
+    }
+    if scores.len() != poll_option_ids.len() {
+        return Err("every poll option has to be scored");
+    }
+    let (min_score, max_score) = score_range;
+    if scores.values().any(|x| *x < min_score || *x > max_score) {
+        return Err("score is out of range");
+    }
+    Ok(())
+}
+
+/// marks all votes and the ballot of the voter in the poll as retracted
+/// and returns the retracted votes
+pub(super) async fn retract_votes(
+    connection: &mut sqlx::PgConnection,
+    poll_id: i64,
+    voter: &str,
+) -> sqlx::Result<Vec<models::PollVote>> {
+    sqlx::query!(
+        r#"update poll_ballot set retracted_at = now()
+        where poll_id = $1 and voter = $2 and retracted_at is null"#,
+        poll_id,
+        voter,
+    )
+    .execute(&mut *connection)
+    .await?;
+    sqlx::query_as!(
+        models::PollVote,
+        r#"update poll_vote set retracted_at = now()
+        where poll_id = $1 and voter = $2 and retracted_at is null
+        returning id, option_id, ip_address, created_at, rank, score, ballot_id, retracted_at"#,
+        poll_id,
+        voter,
+    )
+    .fetch_all(&mut *connection)
+    .await
+}
+
+// used for submitting a new ballot as well as for replacing the current one,
+// in the latter case the previous votes are retracted in the same transaction
+async fn store_ballot(
+    pool: &sqlx::PgPool,
+    id: i64,
+    voter: &Voter,
+    request_data: BallotPostRequestData,
+    replace_existing: bool,
+) -> HttpResponse {
+    // everything from reading the poll to inserting the last vote happens
+    // in one transaction, so either the whole ballot is stored or nothing
+    // if the transaction is dropped on an early return, it is rolled back
+    let transaction_result = pool.begin().await;
+    let mut transaction = unwrap_or_log_and_internal_server_error_response!(
+        transaction_result,
+        "internal server error"
+    );
+
+    // for share, so the poll can't be changed until the ballot is stored
+    let poll_result = sqlx::query!(
+        r#"select poll_type as "poll_type!: models::PollType", opens_at, timeout_at,
+        min_score, max_score, min_choices, max_choices,
+        array(select id from poll_option where poll_id = poll.id) as "option_ids!"
+        from poll where id = $1
+        for share"#,
+        &id as &i64
+    )
+    .fetch_optional(transaction.as_mut())
+    .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+    let poll = match poll {
+        Some(poll) => poll,
+        None => return HttpResponse::NotFound().json(Message("no such poll")),
+    };
+    if poll.opens_at > chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is not open yet"));
+    }
+    if poll.timeout_at <= chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is closed"));
+    }
+
+    // the ballot is turned into one vote per option,
+    // which is ranked or scored depending on the poll type
+    let (option_ids, ranks, scores): (Vec<i64>, Vec<Option<i32>>, Vec<Option<i32>>) =
+        match (&poll.poll_type, poll.min_score, poll.max_score) {
+            (models::PollType::Single | models::PollType::Multiple, _, _) => {
+                if let Err(e) =
+                    are_ballot_option_ids_valid(&request_data.option_ids, &poll.option_ids)
+                {
+                    return HttpResponse::BadRequest().json(Message(e));
+                }
+                let choice_limits = if poll.poll_type == models::PollType::Single {
+                    (1, 1)
+                } else {
+                    (
+                        poll.min_choices.unwrap_or(1) as usize,
+                        poll.max_choices.unwrap_or(poll.option_ids.len() as i32) as usize,
+                    )
+                };
+                if let Err(e) = is_choice_count_valid(request_data.option_ids.len(), choice_limits)
+                {
+                    return HttpResponse::BadRequest().json(Message(e));
+                }
+                let ranks = vec![None; request_data.option_ids.len()];
+                let scores = vec![None; request_data.option_ids.len()];
+                (request_data.option_ids, ranks, scores)
+            }
+            (models::PollType::Ranked, _, _) => {
+                if let Err(e) =
+                    are_ballot_option_ids_valid(&request_data.option_ids, &poll.option_ids)
+                {
+                    return HttpResponse::BadRequest().json(Message(e));
+                }
+                // the position in the list becomes the rank
+                let ranks = (1..=request_data.option_ids.len() as i32)
+                    .map(Some)
+                    .collect();
+                let scores = vec![None; request_data.option_ids.len()];
+                (request_data.option_ids, ranks, scores)
+            }
+            (models::PollType::Score, Some(min_score), Some(max_score)) => {
+                if let Err(e) = is_score_ballot_valid(
+                    &request_data.scores,
+                    &poll.option_ids,
+                    (min_score, max_score),
+                ) {
+                    return HttpResponse::BadRequest().json(Message(e));
+                }
+                let ranks = vec![None; request_data.scores.len()];
+                let (option_ids, scores) = request_data
+                    .scores
+                    .into_iter()
+                    .map(|(option_id, score)| (option_id, Some(score)))
+                    .unzip();
+                (option_ids, ranks, scores)
+            }
+            (models::PollType::Score, _, _) => {
+                log::error!("score poll {} has no score range", id);
+                return HttpResponse::InternalServerError().json(Message("internal server error"));
+            }
+        };
+
+    if replace_existing {
+        let retract_result = retract_votes(transaction.as_mut(), id, &voter.id).await;
+        let retracted_votes = unwrap_or_log_and_internal_server_error_response!(
+            retract_result,
+            "internal server error"
+        );
+        if retracted_votes.is_empty() {
+            return HttpResponse::NotFound().json(Message("you have not voted in this poll"));
+        }
+    }
+
+    let ballot_result = sqlx::query_as!(
+        models::PollBallot,
+        r#"insert into poll_ballot (poll_id, ip_address, voter) values ($1, $2, $3)
+        returning id, ip_address, created_at, retracted_at"#,
+        &id as &i64,
+        &voter.ip_address,
+        &voter.id,
+    )
+    .fetch_one(transaction.as_mut())
+    .await;
+    let ballot = match ballot_result {
+        Ok(ballot) => ballot,
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return HttpResponse::BadRequest().json(Message("you have already voted in this poll"))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return HttpResponse::InternalServerError().json(Message("internal server error"));
+        }
+    };
+
+    let votes_result = sqlx::query_as!(
+        models::PollVote,
+        r#"insert into poll_vote (option_id, poll_id, poll_type, ip_address, voter, rank, score, ballot_id)
+        select ballot.option_id, $4, $5, $6, $7, ballot.rank, ballot.score, $8
+        from unnest($1::bigint[], $2::integer[], $3::integer[]) as ballot(option_id, rank, score)
+        returning id, option_id, ip_address, created_at, rank, score, ballot_id, retracted_at"#,
+        &option_ids,
+        &ranks as &[Option<i32>],
+        &scores as &[Option<i32>],
+        &id as &i64,
+        &poll.poll_type as &models::PollType,
+        &voter.ip_address,
+        &voter.id,
+        &ballot.id as &i64,
+    )
+    .fetch_all(transaction.as_mut())
+    .await;
+    let votes = match votes_result {
+        Ok(votes) => votes,
+        // single votes cast before via the poll option conflict with the ballot
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return HttpResponse::BadRequest().json(Message("you have already voted in this poll"))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return HttpResponse::InternalServerError().json(Message("internal server error"));
+        }
+    };
+
+    let commit_result = transaction.commit().await;
+    unwrap_or_log_and_internal_server_error_response!(commit_result, "internal server error");
+
+    HttpResponse::Ok().json(BallotPostResponseData { ballot, votes })
+}
+
+async fn post_ballot(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    ballot: web::Json<BallotPostRequestData>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let voter = match Voter::resolve(pool, id, &request).await {
+        Ok(voter) => voter,
+        Err(response) => return response,
+    };
+    voter.respond(store_ballot(pool, id, &voter, ballot.into_inner(), false).await)
+}
+
+async fn put_ballot(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    ballot: web::Json<BallotPostRequestData>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let voter = match Voter::resolve(pool, id, &request).await {
+        Ok(voter) => voter,
+        Err(response) => return response,
+    };
+    if !voter.may_change_ballot() {
+        return HttpResponse::Forbidden().json(Message("ballots of voter tokens can't be changed"));
+    }
+    voter.respond(store_ballot(pool, id, &voter, ballot.into_inner(), true).await)
+}
+
+async fn delete_ballot(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let voter = match Voter::resolve(pool, id, &request).await {
+        Ok(voter) => voter,
+        Err(response) => return response,
+    };
+    if !voter.may_change_ballot() {
+        return HttpResponse::Forbidden().json(Message("ballots of voter tokens can't be changed"));
+    }
+
+    let transaction_result = pool.begin().await;
+    let mut transaction = unwrap_or_log_and_internal_server_error_response!(
+        transaction_result,
+        "internal server error"
+    );
+
+    let poll_result = sqlx::query!(
+        r#"select timeout_at from poll where id = $1 for share"#,
+        &id as &i64
+    )
+    .fetch_optional(transaction.as_mut())
+    .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+    let poll = match poll {
+        Some(poll) => poll,
+        None => return HttpResponse::NotFound().json(Message("no such poll")),
+    };
+    // once a poll is closed its results are final
+    if poll.timeout_at <= chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is closed"));
+    }
+
+    let retract_result = retract_votes(transaction.as_mut(), id, &voter.id).await;
+    let retracted_votes =
+        unwrap_or_log_and_internal_server_error_response!(retract_result, "internal server error");
+    if retracted_votes.is_empty() {
+        return HttpResponse::NotFound().json(Message("you have not voted in this poll"));
+    }
+
+    let commit_result = transaction.commit().await;
+    unwrap_or_log_and_internal_server_error_response!(commit_result, "internal server error");
+
+    HttpResponse::Ok().json(retracted_votes)
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PollResultsResponseData {
+    poll_options: Vec<models::PollOption>,
+    #[serde(flatten)]
+    result: PollResults,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "method", rename_all = "camelCase")]
+enum PollResults {
+    InstantRunoff(tally::InstantRunoffResult),
+    Schulze(tally::SchulzeResult),
+    Score(tally::ScoreResult),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ResultsMethod {
+    InstantRunoff,
+    Schulze,
+    Score,
+}
+
+#[derive(serde::Deserialize)]
+struct ResultsQuery {
+    // defaults to instant runoff for ranked polls and score for score polls
+    method: Option<ResultsMethod>,
+}
+
+async fn retrieve_poll_options(
+    pool: &sqlx::PgPool,
+    poll_id: i64,
+) -> sqlx::Result<Vec<models::PollOption>> {
+    sqlx::query_as!(
+        models::PollOption,
+        r#"select id, name from poll_option where poll_id = $1 order by id"#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// returns the ballots of a ranked poll, each ordered from most to least preferred
+async fn retrieve_ranked_ballots(pool: &sqlx::PgPool, poll_id: i64) -> sqlx::Result<Vec<Vec<i64>>> {
+    let votes = sqlx::query!(
+        r#"select voter, option_id from poll_vote
+        where poll_id = $1 and rank is not null and retracted_at is null
+        order by voter, rank"#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // the votes of one ballot are next to each other because of the ordering
+    let mut ballots: Vec<Vec<i64>> = Vec::new();
+    let mut previous_voter = None;
+    for vote in votes {
+        if previous_voter.as_ref() != Some(&vote.voter) {
+            ballots.push(Vec::new());
+            previous_voter = Some(vote.voter);
+        }
+        if let Some(ballot) = ballots.last_mut() {
+            ballot.push(vote.option_id);
+        }
+    }
+    Ok(ballots)
+}
+
+/// returns every score of a score poll together with the id of the scored option
+async fn retrieve_scores(pool: &sqlx::PgPool, poll_id: i64) -> sqlx::Result<Vec<(i64, i32)>> {
+    let votes = sqlx::query!(
+        r#"select option_id, score as "score!" from poll_vote
+        where poll_id = $1 and score is not null and retracted_at is null"#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(votes.into_iter().map(|x| (x.option_id, x.score)).collect())
+}
+
+async fn get_poll_results(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    query: web::Query<ResultsQuery>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match authorize_poll_access(pool, &path_slug, &request).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_results_access(pool, id, &request).await {
+        return response;
+    }
+
+    let poll_result = sqlx::query!(
+        r#"select poll_type as "poll_type!: models::PollType", min_score, max_score
+        from poll where id = $1"#,
+        &id as &i64
+    )
+    .fetch_optional(pool)
+    .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+    let poll = match poll {
+        Some(poll) => poll,
+        None => return HttpResponse::NotFound().json(Message("no such poll")),
+    };
+
+    let method = match (&poll.poll_type, query.into_inner().method) {
+        (models::PollType::Ranked, None) => ResultsMethod::InstantRunoff,
+        (models::PollType::Score, None) => ResultsMethod::Score,
+        (_, Some(method)) => method,
+        _ => {
+            return HttpResponse::BadRequest().json(Message(
+                "results are only available for ranked and score polls",
+            ))
+        }
+    };
+
+    let poll_options = unwrap_or_log_and_internal_server_error_response!(
+        retrieve_poll_options(pool, id).await,
+        "internal server error"
+    );
+    let option_ids: Vec<i64> = poll_options.iter().map(|x| x.id).collect();
+
+    let result = match (poll.poll_type, method, poll.min_score, poll.max_score) {
+        (models::PollType::Ranked, ResultsMethod::InstantRunoff, _, _) => {
+            let ballots = unwrap_or_log_and_internal_server_error_response!(
+                retrieve_ranked_ballots(pool, id).await,
+                "internal server error"
+            );
+            PollResults::InstantRunoff(tally::instant_runoff(&option_ids, &ballots))
+        }
+        (models::PollType::Ranked, ResultsMethod::Schulze, _, _) => {
+            let ballots = unwrap_or_log_and_internal_server_error_response!(
+                retrieve_ranked_ballots(pool, id).await,
+                "internal server error"
+            );
+            PollResults::Schulze(tally::schulze(&option_ids, &ballots))
+        }
+        (models::PollType::Score, ResultsMethod::Score, Some(min_score), Some(max_score)) => {
+            let votes = unwrap_or_log_and_internal_server_error_response!(
+                retrieve_scores(pool, id).await,
+                "internal server error"
+            );
+            PollResults::Score(tally::score(&option_ids, (min_score, max_score), &votes))
+        }
+        _ => {
+            return HttpResponse::BadRequest()
+                .json(Message("method is not available for this poll type"))
+        }
+    };
+    HttpResponse::Ok().json(PollResultsResponseData {
+        poll_options,
+        result,
+    })
+}
+
+const ADMIN_TOKEN_HEADER: &str = "X-Admin-Token";
+
+/// returns the response to send, if the request does not carry
+/// the admin token of the poll or the session of its owner
+pub(super) async fn authorize_poll_admin(
+    pool: &sqlx::PgPool,
+    poll_id: i64,
+    request: &HttpRequest,
+) -> Result<(), HttpResponse> {
+    let poll_result = sqlx::query!(
+        r#"select admin_token_hash, owner_id from poll where id = $1"#,
+        poll_id
+    )
+    .fetch_optional(pool)
+    .await;
+    let poll = match poll_result {
+        Ok(Some(poll)) => poll,
+        Ok(None) => return Err(HttpResponse::NotFound().json(Message("no such poll"))),
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(HttpResponse::InternalServerError().json(Message("internal server error")));
+        }
+    };
+    if is_poll_admin(
+        pool,
+        poll.admin_token_hash.as_deref(),
+        poll.owner_id,
+        request,
+    )
+    .await?
+    {
+        Ok(())
+    } else if poll.owner_id.is_some() {
+        Err(HttpResponse::Unauthorized().json(Message("invalid admin token or session")))
+    } else {
+        Err(HttpResponse::Unauthorized().json(Message("invalid admin token")))
+    }
+}
+
+/// whether the request carries the admin token or the session of the owner
+async fn is_poll_admin(
+    pool: &sqlx::PgPool,
+    admin_token_hash: Option<&[u8]>,
+    owner_id: Option<i64>,
+    request: &HttpRequest,
+) -> Result<bool, HttpResponse> {
+    if is_admin_token_valid(admin_token_hash, request) {
+        return Ok(true);
+    }
+    // anonymous polls can only be managed with the admin token
+    let Some(owner_id) = owner_id else {
+        return Ok(false);
+    };
+    Ok(authenticate_user(pool, request).await? == Some(owner_id))
+}
+
+// polls created before admin tokens existed don't have one
+fn is_admin_token_valid(admin_token_hash: Option<&[u8]>, request: &HttpRequest) -> bool {
+    let admin_token = request
+        .headers()
+        .get(ADMIN_TOKEN_HEADER)
+        .and_then(|x| x.to_str().ok());
+    match (admin_token_hash, admin_token) {
+        (Some(admin_token_hash), Some(admin_token)) => {
+            admin_token_hash == auth::hash_token(admin_token)
+        }
+        _ => false,
+    }
+}
+
+/// returns the response to send, if the results_visibility of the poll
+/// does not allow the request to see its votes and results
+async fn authorize_results_access(
+    pool: &sqlx::PgPool,
+    poll_id: i64,
+    request: &HttpRequest,
+) -> Result<(), HttpResponse> {
+    // whoever can't be identified as a voter, e.g. without the token of an
+    // invite-only poll, has not voted, but may still see the results otherwise
+    let voter = Voter::resolve(pool, poll_id, request).await.ok();
+    let poll_result = sqlx::query!(
+        r#"select results_visibility as "results_visibility!: models::ResultsVisibility",
+        timeout_at, admin_token_hash, owner_id,
+        exists(select 1 from poll_vote where poll_id = poll.id
+            and voter = $2 and retracted_at is null) as "has_voted!"
+        from poll where id = $1"#,
+        poll_id,
+        voter.as_ref().map(|x| x.id.as_str())
+    )
+    .fetch_optional(pool)
+    .await;
+    let poll = match poll_result {
+        Ok(Some(poll)) => poll,
+        Ok(None) => return Err(HttpResponse::NotFound().json(Message("no such poll"))),
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(HttpResponse::InternalServerError().json(Message("internal server error")));
+        }
+    };
+    if is_poll_admin(
+        pool,
+        poll.admin_token_hash.as_deref(),
+        poll.owner_id,
+        request,
+    )
+    .await?
+    {
+        return Ok(());
+    }
+    match poll.results_visibility {
+        models::ResultsVisibility::Always => Ok(()),
+        models::ResultsVisibility::AfterVoting if poll.has_voted => Ok(()),
+        models::ResultsVisibility::AfterVoting => {
+            Err(HttpResponse::Forbidden().json(Message("results are only visible after voting")))
+        }
+        models::ResultsVisibility::AfterClose if poll.timeout_at <= chrono::Utc::now() => Ok(()),
+        models::ResultsVisibility::AfterClose => Err(HttpResponse::Forbidden()
+            .json(Message("results are only visible after the poll is closed"))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PollOptionPatchRequestData {
+    // existing options are referenced by id, new ones don't have one
+    id: Option<i64>,
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PollPatchRequestData {
+    title: Option<String>,
+    timeout_at: Option<chrono::DateTime<chrono::Utc>>,
+    delete_at: Option<chrono::DateTime<chrono::Utc>>,
+    // the complete list of options the poll should have afterwards,
+    // existing options that are left out are removed
+    poll_options: Option<Vec<PollOptionPatchRequestData>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PollPatchResponseData {
+    #[serde(flatten)]
+    poll: PollResponseData,
+    poll_options: Vec<models::PollOption>,
+}
+
+// once the first vote has arrived, changing the title or existing options
+// would change what voters have voted for, so only new options can be added
+fn are_poll_option_edits_valid(
+    poll_options: &[PollOptionPatchRequestData],
+    existing_poll_options: &[models::PollOption],
+    has_votes: bool,
+) -> Result<(), &'static str> {
+    let names: Vec<String> = poll_options.iter().map(|x| x.name.clone()).collect();
+    are_poll_options_valid(&names)?;
+    for (i, poll_option) in poll_options.iter().enumerate() {
+        let Some(id) = poll_option.id else {
+            continue;
+        };
+        if !existing_poll_options.iter().any(|x| x.id == id) {
+            return Err("no such poll option");
+        }
+        if poll_options[i + 1..].iter().any(|x| x.id == Some(id)) {
+            return Err("poll option ids are not unique");
+        }
+    }
+    if has_votes {
+        let unchanged = existing_poll_options.iter().all(|existing| {
+            poll_options
+                .iter()
+                .any(|x| x.id == Some(existing.id) && x.name == existing.name)
+        });
+        if !unchanged {
+            return Err("poll already has votes, options can only be added");
+        }
+    }
+    Ok(())
+}
+
+async fn patch_poll(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    poll: web::Json<PollPatchRequestData>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match retrieve_poll_id(pool, &path_slug).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    let request_data = poll.into_inner();
+    if let Err(response) = authorize_poll_admin(pool, id, &request).await {
+        return response;
+    }
+
+    if request_data.title.as_ref().is_some_and(|x| x.is_empty()) {
+        return HttpResponse::BadRequest().json(Message("title is empty"));
+    }
+    // closing a poll is done with its own endpoint
+    if let Some(timeout_at) = request_data.timeout_at {
+        if timeout_at < chrono::Utc::now() {
+            return HttpResponse::BadRequest().json(Message("timeout_at is in the past"));
+        }
+    }
+    if let Some(delete_at) = request_data.delete_at {
+        if delete_at < chrono::Utc::now() {
+            return HttpResponse::BadRequest().json(Message("delete_at is in the past"));
+        }
+    }
+
+    let transaction_result = pool.begin().await;
+    let mut transaction = unwrap_or_log_and_internal_server_error_response!(
+        transaction_result,
+        "internal server error"
+    );
+
+    // for update, so no vote can be inserted until the changes are committed,
+    // since the foreign keys of poll_vote have to lock the poll row as well
+    let current_poll_result = sqlx::query!(
+        r#"select title, min_choices, timeout_at,
+        exists(select 1 from poll_vote where poll_id = poll.id) as "has_votes!"
+        from poll where id = $1
+        for update"#,
+        &id as &i64
+    )
+    .fetch_optional(transaction.as_mut())
+    .await;
+    let current_poll = unwrap_or_log_and_internal_server_error_response!(
+        current_poll_result,
+        "internal server error"
+    );
+    let Some(current_poll) = current_poll else {
+        return HttpResponse::NotFound().json(Message("no such poll"));
+    };
+
+    // once a poll is closed its results are final, so it can't be reopened
+    if request_data.timeout_at.is_some() && current_poll.timeout_at <= chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is closed"));
+    }
+
+    if current_poll.has_votes
+        && request_data
+            .title
+            .as_ref()
+            .is_some_and(|x| *x != current_poll.title)
+    {
+        return HttpResponse::BadRequest().json(Message("poll already has votes, title is locked"));
+    }
+
+    if let Some(poll_options) = &request_data.poll_options {
+        let existing_poll_options_result = sqlx::query_as!(
+            models::PollOption,
+            r#"select id, name from poll_option where poll_id = $1"#,
+            &id as &i64
+        )
+        .fetch_all(transaction.as_mut())
+        .await;
+        let existing_poll_options = unwrap_or_log_and_internal_server_error_response!(
+            existing_poll_options_result,
+            "internal server error"
+        );
+        if let Err(e) = are_poll_option_edits_valid(
+            poll_options,
+            &existing_poll_options,
+            current_poll.has_votes,
+        ) {
+            return HttpResponse::BadRequest().json(Message(e));
+        }
+        if current_poll
+            .min_choices
+            .is_some_and(|x| x > poll_options.len() as i32)
+        {
+            return HttpResponse::BadRequest().json(Message(
+                "min_choices is higher than the number of poll options",
+            ));
+        }
+
+        let (kept_ids, kept_names): (Vec<i64>, Vec<String>) = poll_options
+            .iter()
+            .filter_map(|x| x.id.map(|id| (id, x.name.clone())))
+            .unzip();
+        let new_names: Vec<String> = poll_options
+            .iter()
+            .filter(|x| x.id.is_none())
+            .map(|x| x.name.clone())
+            .collect();
+
+        let delete_result = sqlx::query!(
+            r#"delete from poll_option where poll_id = $1 and id <> all($2)"#,
+            &id as &i64,
+            &kept_ids,
+        )
+        .execute(transaction.as_mut())
+        .await;
+        unwrap_or_log_and_internal_server_error_response!(delete_result, "internal server error");
+
+        // options can swap their names, which only conflicts until both are renamed
+        let defer_result = sqlx::query!(r#"set constraints unique_name_poll_id deferred"#)
+            .execute(transaction.as_mut())
+            .await;
+        unwrap_or_log_and_internal_server_error_response!(defer_result, "internal server error");
+
+        let rename_result = sqlx::query!(
+            r#"update poll_option set name = renamed.name
+            from unnest($2::bigint[], $3::text[]) as renamed(id, name)
+            where poll_option.poll_id = $1 and poll_option.id = renamed.id
+            and poll_option.name <> renamed.name"#,
+            &id as &i64,
+            &kept_ids,
+            &kept_names,
+        )
+        .execute(transaction.as_mut())
+        .await;
+        let insert_result = match rename_result {
+            Ok(_) => {
+                sqlx::query!(
+                    r#"insert into poll_option (poll_id, name) select $1, unnest($2::text[])"#,
+                    &id as &i64,
+                    &new_names,
+                )
+                .execute(transaction.as_mut())
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        // checks the deferred constraint now, instead of when committing
+        let check_result = match insert_result {
+            Ok(_) => {
+                sqlx::query!(r#"set constraints unique_name_poll_id immediate"#)
+                    .execute(transaction.as_mut())
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        match check_result {
+            Ok(_) => {}
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                return HttpResponse::BadRequest().json(Message("poll options are not unique"))
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                return HttpResponse::InternalServerError().json(Message("internal server error"));
+            }
+        }
+    }
+
+    let poll_result = sqlx::query_as!(
+        models::Poll,
+        r#"update poll set title = coalesce($2, title),
+        timeout_at = coalesce($3, timeout_at), delete_at = coalesce($4, delete_at)
+        where id = $1
+        returning id, title, poll_type as "poll_type!: models::PollType", created_at, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, slug, unlisted, results_visibility as "results_visibility!: models::ResultsVisibility", voter_identity as "voter_identity!: models::VoterIdentity""#,
+        &id as &i64,
+        request_data.title,
+        request_data.timeout_at,
+        request_data.delete_at,
+    )
+    .fetch_one(transaction.as_mut())
+    .await;
+    let poll = match poll_result {
+        Ok(poll) => poll,
+        // the check constraints of poll compare the new values with the existing ones
+        Err(sqlx::Error::Database(e)) if e.is_check_violation() => {
+            let message = match e.constraint() {
+                Some("check_timeout_at_higher_than_opens_at") => {
+                    "timeout_at is lower than opens_at"
+                }
+                _ => "delete_at is lower than timeout_at",
+            };
+            return HttpResponse::BadRequest().json(Message(message));
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return HttpResponse::InternalServerError().json(Message("internal server error"));
+        }
+    };
+
+    let poll_options_result = sqlx::query_as!(
+        models::PollOption,
+        r#"select id, name from poll_option where poll_id = $1 order by id"#,
+        &id as &i64
+    )
+    .fetch_all(transaction.as_mut())
+    .await;
+    let poll_options = unwrap_or_log_and_internal_server_error_response!(
+        poll_options_result,
+        "internal server error"
+    );
+
+    let commit_result = transaction.commit().await;
+    unwrap_or_log_and_internal_server_error_response!(commit_result, "internal server error");
+
+    HttpResponse::Ok().json(PollPatchResponseData {
+        poll: PollResponseData::from(poll),
+        poll_options,
+    })
+}
+
+async fn close_poll(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match retrieve_poll_id(pool, &path_slug).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_poll_admin(pool, id, &request).await {
+        return response;
+    }
+
+    let poll_result = sqlx::query_as!(
+        models::Poll,
+        r#"update poll set timeout_at = now()
+        where id = $1 and timeout_at > now()
+        returning id, title, poll_type as "poll_type!: models::PollType", created_at, opens_at, timeout_at, delete_at, min_score, max_score, min_choices, max_choices, slug, unlisted, results_visibility as "results_visibility!: models::ResultsVisibility", voter_identity as "voter_identity!: models::VoterIdentity""#,
+        &id as &i64,
+    )
+    .fetch_optional(pool)
+    .await;
+    match poll_result {
+        Ok(Some(poll)) => HttpResponse::Ok().json(PollResponseData::from(poll)),
+        // the poll exists, otherwise the authorization would have failed
+        Ok(None) => HttpResponse::BadRequest().json(Message("poll is already closed")),
+        // scheduled polls can't be closed, timeout_at would be lower than opens_at
+        Err(sqlx::Error::Database(e))
+            if e.constraint() == Some("check_timeout_at_higher_than_opens_at") =>
+        {
+            HttpResponse::BadRequest().json(Message("poll is not open yet"))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            HttpResponse::InternalServerError().json(Message("internal server error"))
+        }
+    }
+}
+
+async fn delete_poll(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let id = match retrieve_poll_id(pool, &path_slug).await {
+        Ok(id) => id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_poll_admin(pool, id, &request).await {
+        return response;
+    }
+
+    // options, ballots and votes are deleted by the cascading foreign keys
+    let delete_result = sqlx::query!(r#"delete from poll where id = $1"#, &id as &i64)
+        .execute(pool)
+        .await;
+    unwrap_or_log_and_internal_server_error_response!(delete_result, "internal server error");
+    HttpResponse::Ok().json(Message("poll has been deleted"))
+}
+
+pub fn configure_routes(config: &mut ServiceConfig) {
+    config.route("", web::get().to(get_polls));
+    // has to be registered before /{id}, otherwise that would match first
+    config.route("/upcoming", web::get().to(get_upcoming_polls));
+    config.route("/search", web::get().to(search_polls));
+    // this could alternatively be done with a header guard that checks for
+    // the accept header, if json, send json, otherwise send an image
+    config.route("/{id}", web::get().to(get_poll));
+    config.route("/{id}/graph", web::get().to(get_poll_graph));
+    config.route("/{id}/votes", web::get().to(get_poll_votes));
+    config.route("/{id}/votes/stream", web::get().to(get_poll_votes_stream));
+    config.route("/{id}/socket", web::get().to(get_poll_socket));
+    config.route("/{id}/ballots", web::post().to(post_ballot));
+    // the ballot of whoever sends the request, identified like when voting
+    config.route("/{id}/ballots/mine", web::put().to(put_ballot));
+    config.route("/{id}/ballots/mine", web::delete().to(delete_ballot));
+    config.route("/{id}/results", web::get().to(get_poll_results));
+    config.route("", web::post().to(post_poll));
+    // these require the admin token, that is returned when creating a poll
+    config.route("/{id}", web::patch().to(patch_poll));
+    config.route("/{id}", web::delete().to(delete_poll));
+    config.route("/{id}/close", web::post().to(close_poll));
+    config.service(web::scope("/{slug}/options").configure(super::option::configure_routes));
+    config.service(
+        web::scope("/{slug}/voter-tokens").configure(super::voter_token::configure_routes),
+    );
 }
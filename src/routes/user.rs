@@ -0,0 +1,231 @@
+use actix_web::{
+    web::{self, ServiceConfig},
+    HttpRequest, HttpResponse, Responder,
+};
+
+use crate::{
+    auth,
+    models::{self, Message},
+    AppData,
+};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+// longer passwords are rejected before hashing, since the cost grows with the length
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// the session token in the Authorization header, as in "Bearer <token>"
+fn session_token(request: &HttpRequest) -> Option<&str> {
+    request
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+}
+
+/// the id of the user who is logged in, None if the request has no session token
+/// an invalid or expired session token is rejected, instead of treating the request
+/// as anonymous, so clients notice they have to log in again
+pub(super) async fn authenticate_user(
+    pool: &sqlx::PgPool,
+    request: &HttpRequest,
+) -> Result<Option<i64>, HttpResponse> {
+    let Some(token) = session_token(request) else {
+        return Ok(None);
+    };
+    let session_result = sqlx::query!(
+        r#"select user_id from user_session where token_hash = $1 and expires_at > now()"#,
+        auth::hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await;
+    match session_result {
+        Ok(Some(session)) => Ok(Some(session.user_id)),
+        Ok(None) => Err(HttpResponse::Unauthorized().json(Message("invalid session token"))),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(HttpResponse::InternalServerError().json(Message("internal server error")))
+        }
+    }
+}
+
+/// like authenticate_user, but the request has to have a session token
+pub(super) async fn require_user(
+    pool: &sqlx::PgPool,
+    request: &HttpRequest,
+) -> Result<i64, HttpResponse> {
+    match authenticate_user(pool, request).await? {
+        Some(user_id) => Ok(user_id),
+        None => Err(HttpResponse::Unauthorized().json(Message("login required"))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserPostRequestData {
+    username: String,
+    password: String,
+}
+
+async fn post_user(
+    app_data: web::Data<AppData>,
+    user: web::Json<UserPostRequestData>,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let request_data = user.into_inner();
+
+    if request_data.username.trim().is_empty() {
+        return HttpResponse::BadRequest().json(Message("username is empty"));
+    }
+    if request_data.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return HttpResponse::BadRequest()
+            .json(Message("password has to be at least 8 characters long"));
+    }
+
+    if request_data.password.chars().count() > MAX_PASSWORD_LENGTH {
+        return HttpResponse::BadRequest()
+            .json(Message("password can't be longer than 128 characters"));
+    }
+
+    let password_hash = match super::hash_password(request_data.password).await {
+        Ok(password_hash) => password_hash,
+        Err(response) => return response,
+    };
+
+    let user_result = sqlx::query_as!(
+        models::User,
+        r#"insert into "user" (username, password_hash) values ($1, $2)
+        returning id, username, created_at"#,
+        request_data.username.trim(),
+        password_hash,
+    )
+    .fetch_one(pool)
+    .await;
+    match user_result {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            HttpResponse::Conflict().json(Message("username is already taken"))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            HttpResponse::InternalServerError().json(Message("internal server error"))
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionPostResponseData {
+    // only the hash is stored, so this is the only time it can be retrieved
+    token: String,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+// logging in, the token has to be sent in the Authorization header as a bearer token
+async fn post_session(
+    app_data: web::Data<AppData>,
+    credentials: web::Json<UserPostRequestData>,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let credentials = credentials.into_inner();
+    // no password this long can be set, so it's wrong without hashing it
+    if credentials.password.chars().count() > MAX_PASSWORD_LENGTH {
+        return HttpResponse::Unauthorized().json(Message("invalid username or password"));
+    }
+
+    let user_result = sqlx::query!(
+        r#"select id, password_hash from "user" where username = $1"#,
+        credentials.username.trim()
+    )
+    .fetch_optional(pool)
+    .await;
+    let user =
+        unwrap_or_log_and_internal_server_error_response!(user_result, "internal server error");
+    // a username that doesn't exist is verified against a dummy hash, so it gets
+    // the same response just as slowly as a wrong password
+    let (user_id, password_hash) = match user {
+        Some(user) => (Some(user.id), user.password_hash),
+        None => (None, auth::dummy_password_hash().to_owned()),
+    };
+    let is_valid = match super::verify_password(credentials.password, password_hash).await {
+        Ok(is_valid) => is_valid,
+        Err(response) => return response,
+    };
+    let Some(user_id) = user_id.filter(|_| is_valid) else {
+        return HttpResponse::Unauthorized().json(Message("invalid username or password"));
+    };
+
+    let token = auth::generate_token();
+    let session_result = sqlx::query!(
+        r#"insert into user_session (user_id, token_hash) values ($1, $2)
+        returning expires_at"#,
+        user_id,
+        auth::hash_token(&token),
+    )
+    .fetch_one(pool)
+    .await;
+    let session =
+        unwrap_or_log_and_internal_server_error_response!(session_result, "internal server error");
+
+    HttpResponse::Ok().json(SessionPostResponseData {
+        token,
+        expires_at: session.expires_at,
+    })
+}
+
+// logging out, only the session of the request ends
+async fn delete_session(app_data: web::Data<AppData>, request: HttpRequest) -> impl Responder {
+    let Some(token) = session_token(&request) else {
+        return HttpResponse::Unauthorized().json(Message("login required"));
+    };
+    let delete_result = sqlx::query!(
+        r#"delete from user_session where token_hash = $1 and expires_at > now()"#,
+        auth::hash_token(token)
+    )
+    .execute(&app_data.pool)
+    .await;
+    match delete_result {
+        Ok(query_result) if query_result.rows_affected() == 0 => {
+            HttpResponse::Unauthorized().json(Message("invalid session token"))
+        }
+        Ok(_) => HttpResponse::Ok().json(Message("logged out")),
+        Err(e) => {
+            log::error!("{}", e);
+            HttpResponse::InternalServerError().json(Message("internal server error"))
+        }
+    }
+}
+
+async fn get_me(app_data: web::Data<AppData>, request: HttpRequest) -> impl Responder {
+    let pool = &app_data.pool;
+    let user_id = match require_user(pool, &request).await {
+        Ok(user_id) => user_id,
+        Err(response) => return response,
+    };
+    let user_result = sqlx::query_as!(
+        models::User,
+        r#"select id, username, created_at from "user" where id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await;
+    match user_result {
+        Ok(user) => HttpResponse::Ok().json(user),
+        Err(e) => {
+            log::error!("{}", e);
+            HttpResponse::InternalServerError().json(Message("internal server error"))
+        }
+    }
+}
+
+pub fn configure_routes(config: &mut ServiceConfig) {
+    config.route("", web::post().to(post_user));
+    config.route("/sessions", web::post().to(post_session));
+    // the session whose token is in the Authorization header
+    config.route("/sessions/current", web::delete().to(delete_session));
+}
+
+/// everything below /me is about the user who is logged in
+pub fn configure_me_routes(config: &mut ServiceConfig) {
+    config.route("", web::get().to(get_me));
+    config.route("/polls", web::get().to(super::poll::get_owned_polls));
+}
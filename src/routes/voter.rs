@@ -0,0 +1,364 @@
+use std::net::IpAddr;
+
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    web, HttpRequest, HttpResponse,
+};
+use sqlx::types::ipnetwork::IpNetwork;
+
+use super::user::authenticate_user;
+use crate::{
+    auth,
+    client_ip::{self, ClientIpHeader},
+    models::{self, Message},
+    AppData,
+};
+
+const VOTER_COOKIE: &str = "voter";
+const VOTER_TOKEN_HEADER: &str = "X-Voter-Token";
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VoterTokenQuery {
+    voter_token: Option<String>,
+}
+
+/// whoever sends a request to vote, identified according to the voter_identity of the poll
+pub(super) struct Voter {
+    // unique per voter within a poll, prefixed with the kind of identity
+    pub id: String,
+    // the network of the client address, it's stored with every vote
+    // regardless of the voter_identity
+    pub ip_address: IpNetwork,
+    // set if the voter has to be sent a new cookie to be recognized
+    cookie: Option<Cookie<'static>>,
+}
+
+impl Voter {
+    /// every path that votes or checks for votes resolves the voter with this,
+    /// so a voter is always identified the same way
+    pub async fn resolve(
+        pool: &sqlx::PgPool,
+        poll_id: i64,
+        request: &HttpRequest,
+    ) -> Result<Voter, HttpResponse> {
+        let Some(app_data) = request.app_data::<web::Data<AppData>>() else {
+            log::error!("AppData is missing");
+            return Err(HttpResponse::InternalServerError().json(Message("internal server error")));
+        };
+        let poll_result = sqlx::query!(
+            r#"select voter_identity as "voter_identity!: models::VoterIdentity",
+            ipv4_prefix_length, ipv6_prefix_length from poll where id = $1"#,
+            poll_id
+        )
+        .fetch_optional(pool)
+        .await;
+        let poll = match poll_result {
+            Ok(Some(poll)) => poll,
+            Ok(None) => return Err(HttpResponse::NotFound().json(Message("no such poll"))),
+            Err(e) => {
+                log::error!("{}", e);
+                return Err(
+                    HttpResponse::InternalServerError().json(Message("internal server error"))
+                );
+            }
+        };
+        let prefix_lengths = prefix_lengths(
+            (poll.ipv4_prefix_length, poll.ipv6_prefix_length),
+            (app_data.ipv4_prefix_length, app_data.ipv6_prefix_length),
+        );
+        let ip_address = client_ip_network(
+            request,
+            &app_data.trusted_proxies,
+            app_data.client_ip_header,
+            prefix_lengths,
+        )?;
+
+        match poll.voter_identity {
+            models::VoterIdentity::IpAddress => {
+                IpAddressIdentity.identify(request, ip_address).await
+            }
+            models::VoterIdentity::Cookie => {
+                CookieIdentity {
+                    secret: &app_data.cookie_secret,
+                }
+                .identify(request, ip_address)
+                .await
+            }
+            models::VoterIdentity::Token => {
+                TokenIdentity { pool, poll_id }
+                    .identify(request, ip_address)
+                    .await
+            }
+            models::VoterIdentity::User => {
+                UserIdentity { pool }.identify(request, ip_address).await
+            }
+        }
+    }
+
+    /// the voter of every address within the network
+    pub fn from_ip_address(ip_address: IpNetwork) -> Voter {
+        Voter {
+            id: format!("ip:{}", ip_address),
+            ip_address,
+            cookie: None,
+        }
+    }
+
+    /// a voter token casts exactly one ballot, that can't be replaced or retracted afterwards
+    pub fn may_change_ballot(&self) -> bool {
+        !self.id.starts_with("token:")
+    }
+
+    /// adds the cookie that identifies the voter to the response, if there is a new one
+    pub fn respond(&self, mut response: HttpResponse) -> HttpResponse {
+        if let Some(cookie) = &self.cookie {
+            if let Err(e) = response.add_cookie(cookie) {
+                log::error!("{}", e);
+            }
+        }
+        response
+    }
+}
+
+/// the prefix lengths of the poll, if it sets them, otherwise the ones of the instance
+fn prefix_lengths(
+    (poll_ipv4, poll_ipv6): (Option<i32>, Option<i32>),
+    (instance_ipv4, instance_ipv6): (u8, u8),
+) -> (u8, u8) {
+    (
+        poll_ipv4.map(|x| x as u8).unwrap_or(instance_ipv4),
+        poll_ipv6.map(|x| x as u8).unwrap_or(instance_ipv6),
+    )
+}
+
+/// the network of the client address, every address within it counts as the same voter
+/// if voters are identified by their ip address
+fn client_ip_network(
+    request: &HttpRequest,
+    trusted_proxies: &[IpNetwork],
+    client_ip_header: ClientIpHeader,
+    (ipv4_prefix_length, ipv6_prefix_length): (u8, u8),
+) -> Result<IpNetwork, HttpResponse> {
+    let Some(ip_address) = client_ip::client_ip_address(request, trusted_proxies, client_ip_header)
+    else {
+        log::error!("peer_addr is None");
+        return Err(HttpResponse::InternalServerError().json(Message("internal server error")));
+    };
+    // ipv4 clients of a server listening on ipv6 have mapped addresses
+    let ip_address = match ip_address {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip_address),
+        IpAddr::V4(_) => ip_address,
+    };
+    let prefix_length = match ip_address {
+        IpAddr::V4(_) => ipv4_prefix_length,
+        IpAddr::V6(_) => ipv6_prefix_length,
+    };
+    // the host bits are cleared, so all addresses of the network are stored the same
+    IpNetwork::new(ip_address, prefix_length)
+        .and_then(|x| IpNetwork::new(x.network(), prefix_length))
+        .map_err(|e| {
+            log::error!("{}", e);
+            HttpResponse::InternalServerError().json(Message("internal server error"))
+        })
+}
+
+/// a way to tell the voters of a poll apart
+trait VoterIdentityStrategy {
+    async fn identify(
+        &self,
+        request: &HttpRequest,
+        ip_address: IpNetwork,
+    ) -> Result<Voter, HttpResponse>;
+}
+
+// voters behind the same network, e.g. an office behind a NAT, are one voter
+struct IpAddressIdentity;
+
+impl VoterIdentityStrategy for IpAddressIdentity {
+    async fn identify(
+        &self,
+        _request: &HttpRequest,
+        ip_address: IpNetwork,
+    ) -> Result<Voter, HttpResponse> {
+        Ok(Voter::from_ip_address(ip_address))
+    }
+}
+
+// a random id in a cookie, that is signed, so voters can't pick other ids
+// clearing the cookie allows voting again, so this is only meant for polls
+// where convenience matters more than preventing that
+struct CookieIdentity<'a> {
+    secret: &'a [u8],
+}
+
+impl VoterIdentityStrategy for CookieIdentity<'_> {
+    async fn identify(
+        &self,
+        request: &HttpRequest,
+        ip_address: IpNetwork,
+    ) -> Result<Voter, HttpResponse> {
+        let existing_id = request
+            .cookie(VOTER_COOKIE)
+            .and_then(|x| auth::verify_signed(self.secret, x.value()).map(|x| x.to_string()));
+        if let Some(id) = existing_id {
+            return Ok(Voter {
+                id: format!("cookie:{}", id),
+                ip_address,
+                cookie: None,
+            });
+        }
+        let id = auth::generate_token();
+        let cookie = Cookie::build(VOTER_COOKIE, auth::sign(self.secret, &id))
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .max_age(Duration::days(365))
+            .finish();
+        Ok(Voter {
+            id: format!("cookie:{}", id),
+            ip_address,
+            cookie: Some(cookie),
+        })
+    }
+}
+
+// a token minted by the creator of the poll, either in the X-Voter-Token header
+// or in the voterToken query parameter, since browsers can't send headers with websockets
+struct TokenIdentity<'a> {
+    pool: &'a sqlx::PgPool,
+    poll_id: i64,
+}
+
+impl VoterIdentityStrategy for TokenIdentity<'_> {
+    async fn identify(
+        &self,
+        request: &HttpRequest,
+        ip_address: IpNetwork,
+    ) -> Result<Voter, HttpResponse> {
+        let header_token = request
+            .headers()
+            .get(VOTER_TOKEN_HEADER)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_string());
+        let token = header_token.or_else(|| {
+            web::Query::<VoterTokenQuery>::from_query(request.query_string())
+                .ok()
+                .and_then(|x| x.into_inner().voter_token)
+        });
+        let Some(token) = token else {
+            return Err(HttpResponse::Unauthorized().json(Message("poll requires a voter token")));
+        };
+        let voter_token_result = sqlx::query!(
+            r#"select id from voter_token
+            where poll_id = $1 and token_hash = $2 and revoked_at is null"#,
+            self.poll_id,
+            auth::hash_token(&token),
+        )
+        .fetch_optional(self.pool)
+        .await;
+        match voter_token_result {
+            Ok(Some(voter_token)) => Ok(Voter {
+                id: format!("token:{}", voter_token.id),
+                ip_address,
+                cookie: None,
+            }),
+            Ok(None) => Err(HttpResponse::Unauthorized().json(Message("invalid voter token"))),
+            Err(e) => {
+                log::error!("{}", e);
+                Err(HttpResponse::InternalServerError().json(Message("internal server error")))
+            }
+        }
+    }
+}
+
+// the account of whoever is logged in, so voting works the same on every device
+struct UserIdentity<'a> {
+    pool: &'a sqlx::PgPool,
+}
+
+impl VoterIdentityStrategy for UserIdentity<'_> {
+    async fn identify(
+        &self,
+        request: &HttpRequest,
+        ip_address: IpNetwork,
+    ) -> Result<Voter, HttpResponse> {
+        match authenticate_user(self.pool, request).await? {
+            Some(user_id) => Ok(Voter {
+                id: format!("user:{}", user_id),
+                ip_address,
+                cookie: None,
+            }),
+            None => Err(HttpResponse::Unauthorized().json(Message("poll requires logging in"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use actix_web::test::TestRequest;
+    use sqlx::types::ipnetwork::IpNetwork;
+
+    use super::{client_ip_network, prefix_lengths};
+    use crate::client_ip::ClientIpHeader;
+
+    fn network_of(peer: &str, prefix_lengths: (u8, u8)) -> IpNetwork {
+        let peer: IpAddr = peer.parse().unwrap();
+        let request = TestRequest::default()
+            .peer_addr(SocketAddr::new(peer, 4711))
+            .to_http_request();
+        client_ip_network(&request, &[], ClientIpHeader::XForwardedFor, prefix_lengths).unwrap()
+    }
+
+    #[test]
+    fn groups_ipv6_clients_by_64_bit_networks() {
+        let network = network_of("2001:db8:1:2:aaaa:bbbb:cccc:dddd", (32, 64));
+
+        assert_eq!(network, "2001:db8:1:2::/64".parse().unwrap());
+        assert_eq!(
+            network_of("2001:db8:1:2::1", (32, 64)),
+            network,
+            "addresses of the same /64 are the same voter"
+        );
+        assert_ne!(network_of("2001:db8:1:3::1", (32, 64)), network);
+    }
+
+    #[test]
+    fn groups_ipv6_clients_by_56_bit_networks() {
+        let network = network_of("2001:db8:1:2aa::1", (32, 56));
+
+        assert_eq!(network, "2001:db8:1:200::/56".parse().unwrap());
+        assert_eq!(network_of("2001:db8:1:2ff::1", (32, 56)), network);
+        assert_ne!(network_of("2001:db8:1:300::1", (32, 56)), network);
+    }
+
+    #[test]
+    fn clears_the_host_bits() {
+        assert_eq!(
+            network_of("192.0.2.43", (24, 64)),
+            "192.0.2.0/24".parse().unwrap()
+        );
+        assert_eq!(
+            network_of("192.0.2.43", (32, 64)),
+            "192.0.2.43/32".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn counts_ipv4_mapped_clients_as_ipv4() {
+        assert_eq!(
+            network_of("::ffff:192.0.2.43", (24, 64)),
+            "192.0.2.0/24".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn prefix_lengths_of_the_poll_override_the_instance() {
+        assert_eq!(prefix_lengths((Some(24), Some(56)), (32, 64)), (24, 56));
+        assert_eq!(prefix_lengths((None, Some(48)), (32, 64)), (32, 48));
+        assert_eq!(prefix_lengths((Some(16), None), (32, 64)), (16, 64));
+        assert_eq!(prefix_lengths((None, None), (32, 64)), (32, 64));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    web::{self, ServiceConfig},
+    HttpRequest, HttpResponse, Responder,
+};
+
+use super::{
+    poll::{authorize_poll_admin, retract_votes},
+    retrieve_poll_id,
+};
+use crate::{
+    auth,
+    models::{self, Message},
+    AppData,
+};
+
+const MAX_VOTER_TOKENS: i64 = 1000;
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VoterTokensPostRequestData {
+    count: i64,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct VoterTokenPostResponseData {
+    id: i64,
+    created_at: chrono::DateTime<chrono::Utc>,
+    // only the hash is stored, so this is the only time it can be retrieved
+    token: String,
+}
+
+// the creator distributes the tokens, every token can cast exactly one ballot,
+// that can't be replaced or retracted afterwards
+async fn post_voter_tokens(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request_data: web::Json<VoterTokensPostRequestData>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let poll_id = match retrieve_poll_id(pool, &path_slug).await {
+        Ok(poll_id) => poll_id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_poll_admin(pool, poll_id, &request).await {
+        return response;
+    }
+    if !(1..=MAX_VOTER_TOKENS).contains(&request_data.count) {
+        return HttpResponse::BadRequest().json(Message("count has to be between 1 and 1000"));
+    }
+
+    let poll_result = sqlx::query!(
+        r#"select voter_identity as "voter_identity!: models::VoterIdentity", timeout_at
+        from poll where id = $1"#,
+        poll_id
+    )
+    .fetch_one(pool)
+    .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+    if poll.voter_identity != models::VoterIdentity::Token {
+        return HttpResponse::BadRequest().json(Message("poll does not use voter tokens"));
+    }
+    if poll.timeout_at <= chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is closed"));
+    }
+
+    // the tokens by their hash, to match them with the inserted rows
+    let mut tokens: HashMap<Vec<u8>, String> = (0..request_data.count)
+        .map(|_| {
+            let token = auth::generate_token();
+            (auth::hash_token(&token), token)
+        })
+        .collect();
+    let token_hashes: Vec<Vec<u8>> = tokens.keys().cloned().collect();
+    let voter_tokens_result = sqlx::query!(
+        r#"insert into voter_token (poll_id, token_hash)
+        select $1, token_hash from unnest($2::bytea[]) as x(token_hash)
+        returning id, created_at, token_hash"#,
+        poll_id,
+        &token_hashes,
+    )
+    .fetch_all(pool)
+    .await;
+    let voter_tokens = unwrap_or_log_and_internal_server_error_response!(
+        voter_tokens_result,
+        "internal server error"
+    );
+
+    let response_data: Vec<VoterTokenPostResponseData> = voter_tokens
+        .into_iter()
+        .filter_map(|voter_token| {
+            Some(VoterTokenPostResponseData {
+                id: voter_token.id,
+                created_at: voter_token.created_at,
+                token: tokens.remove(&voter_token.token_hash)?,
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(response_data)
+}
+
+// only whether tokens have been redeemed or revoked, the tokens can't be retrieved again
+async fn get_voter_tokens(
+    app_data: web::Data<AppData>,
+    path_slug: web::Path<String>,
+    request: HttpRequest,
+) -> impl Responder {
+    let pool = &app_data.pool;
+    let poll_id = match retrieve_poll_id(pool, &path_slug).await {
+        Ok(poll_id) => poll_id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_poll_admin(pool, poll_id, &request).await {
+        return response;
+    }
+
+    let voter_tokens_result = sqlx::query_as!(
+        models::VoterToken,
+        r#"select id, created_at, revoked_at,
+        (select min(created_at) from poll_vote
+            where poll_id = voter_token.poll_id and voter = 'token:' || voter_token.id
+            and retracted_at is null) as redeemed_at
+        from voter_token where poll_id = $1 order by id"#,
+        poll_id
+    )
+    .fetch_all(pool)
+    .await;
+    match voter_tokens_result {
+        Ok(voter_tokens) => HttpResponse::Ok().json(voter_tokens),
+        Err(e) => {
+            log::error!("{}", e);
+            HttpResponse::InternalServerError().json(Message("internal server error"))
+        }
+    }
+}
+
+// the votes of a revoked token don't count anymore,
+// e.g. if it has been given to the wrong person
+async fn delete_voter_token(
+    app_data: web::Data<AppData>,
+    path: web::Path<(String, i64)>,
+    request: HttpRequest,
+) -> impl Responder {
+    let (slug, id) = path.into_inner();
+    let pool = &app_data.pool;
+    let poll_id = match retrieve_poll_id(pool, &slug).await {
+        Ok(poll_id) => poll_id,
+        Err(response) => return response,
+    };
+    if let Err(response) = authorize_poll_admin(pool, poll_id, &request).await {
+        return response;
+    }
+
+    let transaction_result = pool.begin().await;
+    let mut transaction = unwrap_or_log_and_internal_server_error_response!(
+        transaction_result,
+        "internal server error"
+    );
+
+    let poll_result = sqlx::query!(
+        r#"select timeout_at from poll where id = $1 for share"#,
+        poll_id
+    )
+    .fetch_one(transaction.as_mut())
+    .await;
+    let poll =
+        unwrap_or_log_and_internal_server_error_response!(poll_result, "internal server error");
+    // once a poll is closed its results are final
+    if poll.timeout_at <= chrono::Utc::now() {
+        return HttpResponse::Forbidden().json(Message("poll is closed"));
+    }
+
+    // redeemed_at is from before the votes are retracted,
+    // so it tells whether revoking the token retracted a ballot
+    let voter_token_result = sqlx::query_as!(
+        models::VoterToken,
+        r#"update voter_token set revoked_at = now()
+        where id = $1 and poll_id = $2 and revoked_at is null
+        returning id, created_at, revoked_at,
+        (select min(created_at) from poll_vote
+            where poll_id = voter_token.poll_id and voter = 'token:' || voter_token.id
+            and retracted_at is null) as redeemed_at"#,
+        id,
+        poll_id
+    )
+    .fetch_optional(transaction.as_mut())
+    .await;
+    let voter_token = unwrap_or_log_and_internal_server_error_response!(
+        voter_token_result,
+        "internal server error"
+    );
+    let Some(voter_token) = voter_token else {
+        return HttpResponse::NotFound().json(Message("no such voter token"));
+    };
+
+    let retract_result = retract_votes(
+        transaction.as_mut(),
+        poll_id,
+        &format!("token:{}", voter_token.id),
+    )
+    .await;
+    unwrap_or_log_and_internal_server_error_response!(retract_result, "internal server error");
+
+    let commit_result = transaction.commit().await;
+    unwrap_or_log_and_internal_server_error_response!(commit_result, "internal server error");
+
+    HttpResponse::Ok().json(voter_token)
+}
+
+/// registered below the slug of the poll the tokens belong to,
+/// all of these require the admin token of the poll
+pub fn configure_routes(config: &mut ServiceConfig) {
+    config.route("", web::post().to(post_voter_tokens));
+    config.route("", web::get().to(get_voter_tokens));
+    config.route("/{id}", web::delete().to(delete_voter_token));
+}
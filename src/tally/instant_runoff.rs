@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::OptionTally;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstantRunoffRound {
+    pub tallies: Vec<OptionTally>,
+    // ballots that don't rank any of the remaining options anymore
+    pub exhausted: i64,
+    pub eliminated: Vec<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstantRunoffResult {
+    pub rounds: Vec<InstantRunoffRound>,
+    // usually a single option, however if all remaining options are tied
+    // they are all returned and if there are no ballots this is empty
+    pub winners: Vec<i64>,
+}
+
+/// repeatedly eliminates the option with the fewest first preferences,
+/// until one option has the majority of the remaining ballots
+pub fn instant_runoff(option_ids: &[i64], ballots: &[Vec<i64>]) -> InstantRunoffResult {
+    let mut remaining = option_ids.to_vec();
+    let mut rounds = Vec::new();
+
+    while !remaining.is_empty() {
+        let mut counts: HashMap<i64, i64> = remaining.iter().map(|id| (*id, 0)).collect();
+        let mut exhausted = 0;
+        for ballot in ballots {
+            // a ballot counts for its most preferred option that is still in the race
+            match ballot.iter().find(|id| counts.contains_key(id)) {
+                Some(id) => *counts.entry(*id).or_default() += 1,
+                None => exhausted += 1,
+            }
+        }
+        let active_ballots = ballots.len() as i64 - exhausted;
+        let tallies: Vec<OptionTally> = remaining
+            .iter()
+            .map(|id| OptionTally {
+                option_id: *id,
+                count: counts[id],
+            })
+            .collect();
+
+        if let Some(majority) = tallies.iter().find(|x| x.count * 2 > active_ballots) {
+            let winners = vec![majority.option_id];
+            rounds.push(InstantRunoffRound {
+                tallies,
+                exhausted,
+                eliminated: Vec::new(),
+            });
+            return InstantRunoffResult { rounds, winners };
+        }
+
+        let fewest = tallies.iter().map(|x| x.count).min().unwrap_or(0);
+        if tallies.iter().all(|x| x.count == fewest) {
+            // eliminating any of them would be arbitrary, so it's a tie
+            let winners = if active_ballots == 0 {
+                Vec::new()
+            } else {
+                remaining
+            };
+            rounds.push(InstantRunoffRound {
+                tallies,
+                exhausted,
+                eliminated: Vec::new(),
+            });
+            return InstantRunoffResult { rounds, winners };
+        }
+
+        let mut candidates: Vec<i64> = tallies
+            .iter()
+            .filter(|x| x.count == fewest)
+            .map(|x| x.option_id)
+            .collect();
+        // a tie for the last place is broken by the previous rounds, starting with
+        // the latest one, the option with fewer votes in that round is eliminated
+        for round in rounds.iter().rev() {
+            if candidates.len() == 1 {
+                break;
+            }
+            let count = |id: &i64| {
+                round
+                    .tallies
+                    .iter()
+                    .find(|x| x.option_id == *id)
+                    .map_or(0, |x| x.count)
+            };
+            let fewest = candidates.iter().map(count).min().unwrap_or(0);
+            candidates.retain(|id| count(id) == fewest);
+        }
+        // if the options were tied in every round, the one with the highest id,
+        // i.e. the one that was added last, is eliminated
+        let eliminated = candidates.into_iter().max();
+
+        remaining.retain(|id| Some(*id) != eliminated);
+        rounds.push(InstantRunoffRound {
+            tallies,
+            exhausted,
+            eliminated: eliminated.into_iter().collect(),
+        });
+    }
+
+    InstantRunoffResult {
+        rounds,
+        winners: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::instant_runoff;
+
+    const A: i64 = 1;
+    const B: i64 = 2;
+    const C: i64 = 3;
+    const D: i64 = 4;
+
+    fn repeat(ballot: &[i64], times: usize) -> Vec<Vec<i64>> {
+        vec![ballot.to_vec(); times]
+    }
+
+    #[test]
+    fn eliminates_one_option_of_a_tie_for_last_place() {
+        let ballots = [repeat(&[A], 4), repeat(&[B, C], 3), repeat(&[C, B], 3)].concat();
+
+        let result = instant_runoff(&[A, B, C], &ballots);
+
+        assert_eq!(result.rounds[0].eliminated, vec![C]);
+        assert_eq!(result.winners, vec![B]);
+    }
+
+    #[test]
+    fn breaks_ties_for_last_place_with_previous_rounds() {
+        let ballots = [
+            repeat(&[A, C], 1),
+            repeat(&[B], 3),
+            repeat(&[C], 2),
+            repeat(&[D], 4),
+        ]
+        .concat();
+
+        let result = instant_runoff(&[A, B, C, D], &ballots);
+
+        assert_eq!(result.rounds[0].eliminated, vec![A]);
+        // B and C both have 3 votes now, but C had fewer in the first round
+        assert_eq!(result.rounds[1].eliminated, vec![C]);
+        assert_eq!(result.winners, vec![D]);
+    }
+
+    #[test]
+    fn breaks_ties_for_last_place_without_previous_rounds_by_id() {
+        let ballots = [repeat(&[A], 2), repeat(&[B], 2), repeat(&[C], 3)].concat();
+
+        let result = instant_runoff(&[A, B, C], &ballots);
+
+        assert_eq!(result.rounds[0].eliminated, vec![B]);
+    }
+
+    #[test]
+    fn ballots_without_remaining_options_are_exhausted() {
+        let ballots = [repeat(&[A], 2), repeat(&[B], 1), repeat(&[C], 1)].concat();
+
+        let result = instant_runoff(&[A, B, C], &ballots);
+
+        assert_eq!(result.rounds[0].exhausted, 0);
+        assert_eq!(result.rounds[0].eliminated, vec![C]);
+        // A has 2 of the 3 ballots that are left
+        assert_eq!(result.rounds[1].exhausted, 1);
+        assert_eq!(result.winners, vec![A]);
+    }
+
+    #[test]
+    fn ties_all_remaining_options_with_equal_votes() {
+        let ballots = [repeat(&[A], 2), repeat(&[B], 2), repeat(&[C], 1)].concat();
+
+        let result = instant_runoff(&[A, B, C], &ballots);
+
+        assert_eq!(result.rounds.len(), 2);
+        assert_eq!(result.rounds[1].exhausted, 1);
+        assert!(result.rounds[1].eliminated.is_empty());
+        assert_eq!(result.winners, vec![A, B]);
+    }
+
+    #[test]
+    fn has_no_winners_without_ballots() {
+        let result = instant_runoff(&[A, B], &[]);
+
+        assert_eq!(result.rounds.len(), 1);
+        assert!(result.winners.is_empty());
+    }
+}
@@ -0,0 +1,21 @@
+use serde::Serialize;
+
+// the functions in here only work with option ids and don't know anything
+// about the database, so the routes have to fetch and convert the data first
+// for ranked polls every ballot is a list of option ids,
+// ordered from most to least preferred
+
+mod instant_runoff;
+mod schulze;
+mod score;
+
+pub use instant_runoff::{instant_runoff, InstantRunoffResult};
+pub use schulze::{schulze, SchulzeResult};
+pub use score::{score, ScoreResult};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptionTally {
+    pub option_id: i64,
+    pub count: i64,
+}
@@ -0,0 +1,171 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchulzeResult {
+    // rows and columns of both matrices are in the order of this list
+    pub option_ids: Vec<i64>,
+    // pairwise_preferences[i][j] is the number of ballots
+    // that prefer option i over option j
+    pub pairwise_preferences: Vec<Vec<i64>>,
+    // strongest_paths[i][j] is the strength of the strongest path
+    // from option i to option j, 0 if there is none
+    pub strongest_paths: Vec<Vec<i64>>,
+    // more than one if options are tied, empty if there are no ballots
+    pub winners: Vec<i64>,
+}
+
+/// options that are not on a ballot are considered less preferred
+/// than all ranked ones and equally preferred among each other
+pub fn schulze(option_ids: &[i64], ballots: &[Vec<i64>]) -> SchulzeResult {
+    let option_count = option_ids.len();
+
+    let mut pairwise_preferences = vec![vec![0; option_count]; option_count];
+    for ballot in ballots {
+        // the position on the ballot for every option, None if unranked
+        let ranks: Vec<Option<usize>> = option_ids
+            .iter()
+            .map(|id| ballot.iter().position(|x| x == id))
+            .collect();
+        for i in 0..option_count {
+            for j in 0..option_count {
+                let prefers_i = match (ranks[i], ranks[j]) {
+                    (Some(rank_i), Some(rank_j)) => rank_i < rank_j,
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                };
+                if prefers_i {
+                    pairwise_preferences[i][j] += 1;
+                }
+            }
+        }
+    }
+
+    // a direct link only exists if it's won
+    let mut strongest_paths = vec![vec![0; option_count]; option_count];
+    for i in 0..option_count {
+        for j in 0..option_count {
+            if i != j && pairwise_preferences[i][j] > pairwise_preferences[j][i] {
+                strongest_paths[i][j] = pairwise_preferences[i][j];
+            }
+        }
+    }
+    // a path is as strong as its weakest link,
+    // this is a variant of the Floyd–Warshall algorithm
+    for i in 0..option_count {
+        for j in 0..option_count {
+            if i == j {
+                continue;
+            }
+            for k in 0..option_count {
+                if i == k || j == k {
+                    continue;
+                }
+                let through_i = strongest_paths[j][i].min(strongest_paths[i][k]);
+                if through_i > strongest_paths[j][k] {
+                    strongest_paths[j][k] = through_i;
+                }
+            }
+        }
+    }
+
+    let winners = if ballots.is_empty() {
+        Vec::new()
+    } else {
+        // an option wins, if no other option has a stronger path to it,
+        // than it has to that option
+        (0..option_count)
+            .filter(|i| (0..option_count).all(|j| strongest_paths[*i][j] >= strongest_paths[j][*i]))
+            .map(|i| option_ids[i])
+            .collect()
+    };
+
+    SchulzeResult {
+        option_ids: option_ids.to_vec(),
+        pairwise_preferences,
+        strongest_paths,
+        winners,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::schulze;
+
+    const A: i64 = 1;
+    const B: i64 = 2;
+    const C: i64 = 3;
+    const D: i64 = 4;
+    const E: i64 = 5;
+
+    fn repeat(ballot: &[i64], times: usize) -> Vec<Vec<i64>> {
+        vec![ballot.to_vec(); times]
+    }
+
+    // https://en.wikipedia.org/wiki/Schulze_method#Example
+    #[test]
+    fn finds_the_winner_of_the_wikipedia_example() {
+        let ballots = [
+            repeat(&[A, C, B, E, D], 5),
+            repeat(&[A, D, E, C, B], 5),
+            repeat(&[B, E, D, A, C], 8),
+            repeat(&[C, A, B, E, D], 3),
+            repeat(&[C, A, E, B, D], 7),
+            repeat(&[C, B, A, D, E], 2),
+            repeat(&[D, C, E, B, A], 7),
+            repeat(&[E, B, A, D, C], 8),
+        ]
+        .concat();
+
+        let result = schulze(&[A, B, C, D, E], &ballots);
+
+        assert_eq!(result.pairwise_preferences[0], vec![0, 20, 26, 30, 22]);
+        assert_eq!(result.pairwise_preferences[4], vec![23, 27, 21, 31, 0]);
+        assert_eq!(result.strongest_paths[0], vec![0, 28, 28, 30, 24]);
+        assert_eq!(result.strongest_paths[4], vec![25, 28, 28, 31, 0]);
+        assert_eq!(result.winners, vec![E]);
+    }
+
+    #[test]
+    fn ties_all_options_of_a_symmetric_cycle() {
+        // rock, paper, scissors, every option beats another 2 to 1
+        let ballots = vec![vec![A, B, C], vec![B, C, A], vec![C, A, B]];
+
+        let result = schulze(&[A, B, C], &ballots);
+
+        assert_eq!(result.pairwise_preferences[0], vec![0, 2, 1]);
+        assert_eq!(result.strongest_paths[0], vec![0, 2, 2]);
+        assert_eq!(result.winners, vec![A, B, C]);
+    }
+
+    #[test]
+    fn ties_all_options_without_any_preference() {
+        let ballots = vec![vec![A, B], vec![B, A]];
+
+        let result = schulze(&[A, B], &ballots);
+
+        assert_eq!(result.strongest_paths, vec![vec![0, 0], vec![0, 0]]);
+        assert_eq!(result.winners, vec![A, B]);
+    }
+
+    #[test]
+    fn has_no_winners_without_ballots() {
+        let result = schulze(&[A, B, C], &[]);
+
+        assert_eq!(result.pairwise_preferences, vec![vec![0; 3]; 3]);
+        assert!(result.winners.is_empty());
+    }
+
+    #[test]
+    fn prefers_ranked_options_over_unranked_ones() {
+        let ballots = [repeat(&[A], 2), repeat(&[B, C], 1)].concat();
+
+        let result = schulze(&[A, B, C], &ballots);
+
+        assert_eq!(result.pairwise_preferences[0], vec![0, 2, 2]);
+        // unranked options are equally preferred, so only the ballot ranking both counts
+        assert_eq!(result.pairwise_preferences[1][2], 1);
+        assert_eq!(result.pairwise_preferences[2][1], 0);
+        assert_eq!(result.winners, vec![A]);
+    }
+}
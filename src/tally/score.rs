@@ -0,0 +1,160 @@
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreCount {
+    pub score: i32,
+    pub count: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptionScores {
+    pub option_id: i64,
+    pub count: i64,
+    // None if the option has not been rated yet
+    pub average: Option<f64>,
+    pub median: Option<f64>,
+    // how often every score in the range of the poll was given
+    pub distribution: Vec<ScoreCount>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScoreResult {
+    pub options: Vec<OptionScores>,
+    // the options with the highest average, empty if there are no votes
+    pub winners: Vec<i64>,
+}
+
+/// every vote is a pair of option id and score
+pub fn score(option_ids: &[i64], score_range: (i32, i32), votes: &[(i64, i32)]) -> ScoreResult {
+    let (min_score, max_score) = score_range;
+
+    let options: Vec<OptionScores> = option_ids
+        .iter()
+        .map(|option_id| {
+            let mut scores: Vec<i32> = votes
+                .iter()
+                .filter(|(id, _)| id == option_id)
+                .map(|(_, score)| *score)
+                .collect();
+            scores.sort_unstable();
+
+            let count = scores.len();
+            let average = if count == 0 {
+                None
+            } else {
+                Some(scores.iter().map(|x| *x as f64).sum::<f64>() / count as f64)
+            };
+            let median = match count {
+                0 => None,
+                // the average of the two middle scores, if there is no single one
+                // as f64 first, the sum of two scores can overflow an i32
+                _ if count.is_multiple_of(2) => {
+                    Some((scores[count / 2 - 1] as f64 + scores[count / 2] as f64) / 2.0)
+                }
+                _ => Some(scores[count / 2] as f64),
+            };
+            let distribution = (min_score..=max_score)
+                .map(|score| ScoreCount {
+                    score,
+                    count: scores.iter().filter(|x| **x == score).count() as i64,
+                })
+                .collect();
+
+            OptionScores {
+                option_id: *option_id,
+                count: count as i64,
+                average,
+                median,
+                distribution,
+            }
+        })
+        .collect();
+
+    let highest_average = options.iter().filter_map(|x| x.average).reduce(f64::max);
+    let winners = match highest_average {
+        Some(highest_average) => options
+            .iter()
+            .filter(|x| x.average == Some(highest_average))
+            .map(|x| x.option_id)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    ScoreResult { options, winners }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::score;
+
+    const A: i64 = 1;
+    const B: i64 = 2;
+    const C: i64 = 3;
+
+    #[test]
+    fn computes_the_median_of_an_odd_number_of_scores() {
+        let result = score(&[A], (0, 5), &[(A, 5), (A, 1), (A, 2)]);
+
+        assert_eq!(result.options[0].median, Some(2.0));
+        assert_eq!(result.options[0].average, Some(8.0 / 3.0));
+        assert_eq!(result.options[0].count, 3);
+    }
+
+    #[test]
+    fn computes_the_median_of_an_even_number_of_scores() {
+        let result = score(&[A], (0, 5), &[(A, 5), (A, 1), (A, 2), (A, 4)]);
+
+        assert_eq!(result.options[0].median, Some(3.0));
+    }
+
+    #[test]
+    fn computes_the_median_of_scores_near_the_limits() {
+        let result = score(
+            &[A],
+            (i32::MAX - 1, i32::MAX),
+            &[(A, i32::MAX), (A, i32::MAX)],
+        );
+
+        assert_eq!(result.options[0].median, Some(i32::MAX as f64));
+    }
+
+    #[test]
+    fn options_without_scores_have_no_average_or_median() {
+        let result = score(&[A, B], (0, 5), &[(A, 3)]);
+
+        assert_eq!(result.options[1].count, 0);
+        assert_eq!(result.options[1].average, None);
+        assert_eq!(result.options[1].median, None);
+        assert!(result.options[1].distribution.iter().all(|x| x.count == 0));
+        assert_eq!(result.winners, vec![A]);
+    }
+
+    #[test]
+    fn counts_every_score_of_the_range() {
+        let result = score(&[A], (-1, 2), &[(A, 2), (A, -1), (A, 2)]);
+
+        let distribution: Vec<(i32, i64)> = result.options[0]
+            .distribution
+            .iter()
+            .map(|x| (x.score, x.count))
+            .collect();
+        assert_eq!(distribution, vec![(-1, 1), (0, 0), (1, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn ties_options_with_the_same_highest_average() {
+        let result = score(&[A, B, C], (0, 5), &[(A, 4), (B, 3), (B, 5), (C, 1)]);
+
+        assert_eq!(result.winners, vec![A, B]);
+    }
+
+    #[test]
+    fn has_no_winners_without_votes() {
+        let result = score(&[A, B], (0, 5), &[]);
+
+        assert!(result.winners.is_empty());
+    }
+}